@@ -1,4 +1,4 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 /// groundhog: point-in-time snapshot manager for files and databases
 #[derive(Parser, Debug)]
@@ -7,6 +7,12 @@ pub struct Cli {
     /// Target scope name (defaults to current directory scope if omitted)
     #[arg(short = 's', long = "scope")]
     pub scope: Option<String>,
+    /// Fail at once if another groundhog run holds the workspace lock
+    #[arg(long, global = true)]
+    pub no_wait: bool,
+    /// Seconds to wait for the workspace lock (overrides the scope's lock-timeout)
+    #[arg(long, global = true, value_name = "seconds")]
+    pub lock_timeout: Option<u64>,
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -21,7 +27,10 @@ pub enum Commands {
         #[arg(short = 'n', long = "name")]
         name: Option<String>,
         #[arg(long, value_name = "password")]
-        password: Option<String>
+        password: Option<String>,
+        /// Keep snapshot data in this directory (e.g. another disk) instead of .groundhog
+        #[arg(long, value_name = "dir")]
+        store: Option<String>,
     },
 
     /// Create a snapshot with a name; optionally lock with a password
@@ -31,6 +40,12 @@ pub enum Commands {
         /// Optional password to lock/encrypt snapshot
         #[arg(long, value_name = "password")]
         password: Option<String>,
+        /// Threads for scanning and hashing files (overrides the scope's jobs; 0 = one per CPU)
+        #[arg(short = 'j', long, value_name = "n")]
+        jobs: Option<usize>,
+        /// Hash every file, even those unchanged since the last scan
+        #[arg(long)]
+        paranoid: bool,
     },
 
     /// Roll back to a named snapshot
@@ -42,6 +57,22 @@ pub enum Commands {
         /// Roll back to the most recent snapshot
         #[arg(long)]
         latest: bool,
+        /// Threads for scanning and hashing files (overrides the scope's jobs; 0 = one per CPU)
+        #[arg(short = 'j', long, value_name = "n")]
+        jobs: Option<usize>,
+        /// Hash every file, even those unchanged since the last scan
+        #[arg(long)]
+        paranoid: bool,
+    },
+
+    /// Show what changed since the last snapshot
+    Status {
+        /// Threads for scanning and hashing files (overrides the scope's jobs; 0 = one per CPU)
+        #[arg(short = 'j', long, value_name = "n")]
+        jobs: Option<usize>,
+        /// Hash every file, even those unchanged since the last scan
+        #[arg(long)]
+        paranoid: bool,
     },
 
     /// Delete a named snapshot
@@ -51,6 +82,66 @@ pub enum Commands {
     },
 
 
+    /// Remove stored data no longer referenced by any snapshot
+    Gc {
+        /// List what would be removed without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Write a snapshot to a self-contained tar or tar.zst archive
+    Export {
+        /// Name of snapshot
+        name: String,
+        /// Archive to create; compressed with zstd when it ends in .zst or .tzst
+        #[arg(short = 'o', long = "output", value_name = "file")]
+        output: String,
+        /// Give files their recorded modification times instead of the snapshot's time
+        #[arg(long)]
+        preserve_mtimes: bool,
+    },
+
+    /// Add a snapshot from an exported archive to the scope
+    Import {
+        /// Archive created by `export`
+        file: String,
+        /// Name to give the snapshot instead of the one it was exported with
+        #[arg(short = 'n', long = "name")]
+        name: Option<String>,
+    },
+
+    /// Copy snapshots (manifest and data) to a directory or the scope's remote
+    Push {
+        /// Snapshots to copy
+        #[arg(required_unless_present = "all")]
+        names: Vec<String>,
+        /// Copy every snapshot of the scope
+        #[arg(long, conflicts_with = "names")]
+        all: bool,
+        /// Destination directory or URL (defaults to the scope's remote)
+        #[arg(long, value_name = "dir|url")]
+        to: Option<String>,
+    },
+
+    /// Copy snapshots from a directory or the scope's remote into this scope
+    Pull {
+        /// Snapshots to copy (the newest one of each name)
+        #[arg(required_unless_present = "all")]
+        names: Vec<String>,
+        /// Copy every snapshot this scope does not have yet
+        #[arg(long, conflicts_with = "names")]
+        all: bool,
+        /// Source directory or URL (defaults to the scope's remote)
+        #[arg(long, value_name = "dir|url")]
+        from: Option<String>,
+        /// What to do when this scope already has a different snapshot of the same name
+        #[arg(long, value_enum, default_value_t = OnConflict::Error)]
+        on_conflict: OnConflict,
+    },
+
+    /// Check every snapshot against its stored data and report corruption
+    Verify,
+
     /// Drop the current scope and erases all snapshot data
     Drop,
 
@@ -63,11 +154,43 @@ pub enum Commands {
     /// List snapshots in the current workspace
     List,
 
+    /// Show or change settings of the current scope
+    Config {
+        /// Setting to show or change (omit to list all)
+        key: Option<String>,
+        /// New value for the setting
+        value: Option<String>,
+    },
+
     /// List globally defined scopes
     Scopes,
 
+    /// Manage the global scope registry
+    Registry {
+        #[command(subcommand)]
+        action: RegistryAction,
+    },
+
     /// Print CLI version
     Version,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum RegistryAction {
+    /// Restore the registry from a backup (omit the number to list backups)
+    Restore {
+        /// Backup to restore; 1 is the most recent
+        backup: Option<usize>,
+    },
+}
 
+/// How `pull` treats a snapshot whose name is already taken in the scope.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Stop before copying anything
+    Error,
+    /// Leave the snapshot out
+    Skip,
+    /// Pull it as `<name>-2`, `<name>-3`, ...
+    Rename,
+}
@@ -1,33 +1,221 @@
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
 
 use crate::utils::hash::hash_password;
 
+/// Version of the on-disk format of `meta.json` and snapshot manifests.
+/// Bump it together with a new entry in `storage::migrate::MIGRATIONS`.
+pub const FORMAT_VERSION: u32 = 11;
+
 #[derive(Serialize, Deserialize)]
 pub struct GroundHogConfig {
+    pub format_version: u32,
     pub date_created: DateTime<Local>,
     pub last_updated: DateTime<Local>,
     pub snapshots: Vec<Snapshot>,
-    pub hash_tree: TreeNode,
     pub password_hash: Option<String>, // NEW: workspace password
+    #[serde(default)]
+    pub settings: ScopeSettings,
 }
 
 impl GroundHogConfig {
     pub fn new(password: Option<String>) -> Self {
         let now = Local::now();
         Self {
+            format_version: FORMAT_VERSION,
             date_created: now,
             last_updated: now,
             snapshots: Vec::new(),
-            hash_tree: TreeNode { name: "".into(), hash: String::new(), is_dir: true, children: Some(Vec::new()) },
             password_hash: password.as_ref().map(|p| hash_password(p)),
+            settings: ScopeSettings::default(),
         }
     }
 }
 
+/// Per-scope tunables, edited through `groundhog config <key> [value]`.
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScopeSettings {
+    /// Compress stored file contents with zstd.
+    pub compress: bool,
+    /// zstd level used when `compress` is on (1-22).
+    pub compression_level: i32,
+    /// Seconds to wait for another groundhog run to release the workspace lock.
+    pub lock_timeout: u64,
+    /// Remote that new snapshots are uploaded to and missing ones fetched from:
+    /// `s3://bucket[/prefix]` or a directory.
+    pub remote: Option<String>,
+    /// Record access times in snapshots, and restore them with the files.
+    pub record_atime: bool,
+    /// How snapshots treat symbolic links.
+    pub symlinks: SymlinkPolicy,
+    /// Record extended attributes and ACLs, and restore them with the files.
+    pub xattrs: bool,
+    /// Record owning user and group names along with their ids.
+    pub record_owner_names: bool,
+    /// Whether snapshots record FIFOs, sockets and device nodes or leave them out.
+    pub special_files: SpecialFilePolicy,
+    /// Threads that walk and hash the scope's files; 0 uses one per CPU.
+    pub jobs: usize,
+}
+
+/// How snapshots treat FIFOs, sockets and device nodes inside a scope.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecialFilePolicy {
+    /// Record them (type, mode, device number) and recreate them on rollback.
+    #[default]
+    Record,
+    /// Leave them out of snapshots, and alone on rollback.
+    Skip,
+}
+
+impl SpecialFilePolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            SpecialFilePolicy::Record => "record",
+            SpecialFilePolicy::Skip => "skip",
+        }
+    }
+}
+
+/// How snapshots treat symbolic links inside a scope.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Record the link itself and restore it as a link.
+    #[default]
+    Preserve,
+    /// Record whatever the link points to, as if it were there.
+    Follow,
+    /// Leave links out of snapshots, and alone on rollback.
+    Skip,
+}
+
+impl SymlinkPolicy {
+    fn as_str(self) -> &'static str {
+        match self {
+            SymlinkPolicy::Preserve => "preserve",
+            SymlinkPolicy::Follow => "follow",
+            SymlinkPolicy::Skip => "skip",
+        }
+    }
+}
+
+impl Default for ScopeSettings {
+    fn default() -> Self {
+        Self {
+            compress: false,
+            compression_level: 3,
+            lock_timeout: 30,
+            remote: None,
+            record_atime: false,
+            symlinks: SymlinkPolicy::Preserve,
+            xattrs: false,
+            record_owner_names: false,
+            special_files: SpecialFilePolicy::Record,
+            jobs: 0,
+        }
+    }
+}
+
+impl ScopeSettings {
+    /// All settings as (key, value) pairs, in display order.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("compress", self.compress.to_string()),
+            ("compression-level", self.compression_level.to_string()),
+            ("lock-timeout", self.lock_timeout.to_string()),
+            ("remote", self.remote.clone().unwrap_or_else(|| "none".to_string())),
+            ("record-atime", self.record_atime.to_string()),
+            ("symlinks", self.symlinks.as_str().to_string()),
+            ("xattrs", self.xattrs.to_string()),
+            ("record-owner-names", self.record_owner_names.to_string()),
+            ("special-files", self.special_files.as_str().to_string()),
+            ("jobs", if self.jobs == 0 { "auto".to_string() } else { self.jobs.to_string() }),
+        ]
+    }
+
+    pub fn get(&self, key: &str) -> Result<String> {
+        self.entries()
+            .into_iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+            .ok_or_else(|| anyhow!("unknown setting '{}'", key))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "compress" => self.compress = parse_bool(value)?,
+            "compression-level" => {
+                let level: i32 = value
+                    .parse()
+                    .map_err(|_| anyhow!("compression-level must be a number"))?;
+                if !(1..=22).contains(&level) {
+                    return Err(anyhow!("compression-level must be between 1 and 22"));
+                }
+                self.compression_level = level;
+            }
+            "lock-timeout" => {
+                self.lock_timeout = value
+                    .parse()
+                    .map_err(|_| anyhow!("lock-timeout must be a number of seconds"))?;
+            }
+            "remote" => {
+                self.remote = match value {
+                    "" | "none" | "off" => None,
+                    url => Some(url.to_string()),
+                };
+            }
+            "record-atime" => self.record_atime = parse_bool(value)?,
+            "xattrs" => self.xattrs = parse_bool(value)?,
+            "record-owner-names" => self.record_owner_names = parse_bool(value)?,
+            "special-files" => {
+                self.special_files = match value {
+                    "record" => SpecialFilePolicy::Record,
+                    "skip" => SpecialFilePolicy::Skip,
+                    _ => return Err(anyhow!("special-files must be record or skip")),
+                };
+            }
+            "jobs" => {
+                self.jobs = match value {
+                    "auto" | "0" => 0,
+                    n => n.parse().map_err(|_| anyhow!("jobs must be a number of threads or auto"))?,
+                };
+            }
+            "symlinks" => {
+                self.symlinks = match value {
+                    "preserve" => SymlinkPolicy::Preserve,
+                    "follow" => SymlinkPolicy::Follow,
+                    "skip" => SymlinkPolicy::Skip,
+                    _ => return Err(anyhow!("symlinks must be preserve, follow or skip")),
+                };
+            }
+            _ => return Err(anyhow!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    /// zstd level to store new objects with, or `None` when compression is off.
+    pub fn compression(&self) -> Option<i32> {
+        self.compress.then_some(self.compression_level)
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        _ => Err(anyhow!("expected on/off, got '{}'", value)),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Snapshot {
     pub name: String,
+    /// Snapshot folder, relative to the scope's data dir (see `storage::data_dir`).
     pub directory: String,
     pub kind: SnapshotKind,
     pub locked: bool,
@@ -48,17 +236,127 @@ pub struct Scope {
     pub target: String, // filesystem path or DB address
     pub kind: SnapshotKind,
     pub created_at: DateTime<Local>,
+    /// External store holding the scope's data, if it is not kept in `.groundhog/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<String>,
 }
 
+/// On-disk form of a snapshot's `manifest.json`: the tree plus the format it was written in.
 #[derive(Serialize, Deserialize)]
+pub struct Manifest<T> {
+    pub format_version: u32,
+    pub tree: T,
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct TreeNode {
     /// Entry name (file or directory). Root can be "".
     pub name: String,
     /// Content hash for files; for directories, hash over sorted child entries.
+    /// Recorded extended attributes are hashed in too (see `content_hash`).
     pub hash: String,
     /// true = directory, false = file
     pub is_dir: bool,
+    /// What a non-directory entry is; regular files leave it out.
+    #[serde(default, skip_serializing_if = "NodeKind::is_file")]
+    pub kind: NodeKind,
+    /// Where a symlink points, exactly as stored in the link. Its `hash` is over this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Path of the file this one is a hard link to: the first path of its link group.
+    /// Its content is that file's, and rollback recreates the link.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hard_link: Option<String>,
+    /// Device number of a character or block device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<u64>,
+    /// The file had holes when recorded; rollback leaves its runs of zeros unallocated.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sparse: bool,
+    /// File size in bytes (files only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Unix permission bits (`mode & 0o7777`). `None` off Unix and in older manifests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Owning user and group ids, on Unix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+    /// Owning user and group names; only recorded when the scope's `record-owner-names`
+    /// is on. Rollback prefers them to the ids where they exist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Modification time, in nanoseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
+    /// Access time, likewise; only recorded when the scope's `record-atime` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atime: Option<i64>,
+    /// Extended attributes, POSIX ACLs included, by name with hex-encoded values;
+    /// only recorded when the scope's `xattrs` is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xattrs: Option<BTreeMap<String, String>>,
+    /// Hash of a file's content alone, when `hash` also covers its extended attributes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Content-defined chunks (object hashes, in file order) of a large file stored
+    /// piecewise. `None` when the whole file is stored as the single object `hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Vec<String>>,
     /// Children for directories (sorted by name for stability).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub children: Option<Vec<TreeNode>>,
-}
\ No newline at end of file
+}
+
+/// Kind of a non-directory tree entry.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeKind {
+    /// A regular file, its content kept in the object store.
+    #[default]
+    File,
+    /// A symbolic link, its content (the target) kept in the manifest.
+    Symlink,
+    /// A named pipe.
+    Fifo,
+    /// A Unix domain socket.
+    Socket,
+    /// A character device; its `device` number is recorded.
+    CharDevice,
+    /// A block device; its `device` number is recorded.
+    BlockDevice,
+}
+
+impl NodeKind {
+    pub fn is_file(&self) -> bool {
+        *self == NodeKind::File
+    }
+
+    /// FIFOs, sockets and device nodes: entries with no content to store.
+    pub fn is_special(&self) -> bool {
+        matches!(self, NodeKind::Fifo | NodeKind::Socket | NodeKind::CharDevice | NodeKind::BlockDevice)
+    }
+}
+
+impl TreeNode {
+    /// Hash of this file's content: the object key of a file stored whole.
+    pub fn content_hash(&self) -> &str {
+        self.content_hash.as_deref().unwrap_or(&self.hash)
+    }
+
+    /// Store objects holding this file's content: its chunks, or the whole-file hash.
+    /// Entries whose content lives in the manifest (symlinks) have none.
+    pub fn object_hashes(&self) -> Vec<&str> {
+        if !self.kind.is_file() {
+            return Vec::new();
+        }
+        match &self.chunks {
+            Some(chunks) => chunks.iter().map(String::as_str).collect(),
+            None => vec![self.content_hash()],
+        }
+    }
+}
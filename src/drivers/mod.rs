@@ -8,6 +8,7 @@ pub mod sqlite;
 pub mod selector;
 
 pub trait BackendDriver: Send + Sync {
+    #[allow(dead_code)]
     fn name(&self) -> &'static str;
 
     /// Capture a snapshot of the target into the provided snapshot directory.
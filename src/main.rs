@@ -8,8 +8,11 @@ mod registry;
 
 use anyhow::Result;
 use clap::Parser;
+use std::time::Duration;
 
-use cli::{Cli, Commands};
+use cli::{Cli, Commands, RegistryAction};
+use storage::lock::LockWait;
+use utils::hash::ScanFlags;
 
 fn main() {
     if let Err(err) = run() {
@@ -20,32 +23,60 @@ fn main() {
 
 fn run() -> Result<()> {
     let cli = Cli::parse();
+    let wait = LockWait { no_wait: cli.no_wait, timeout: cli.lock_timeout.map(Duration::from_secs) };
 
     match cli.command {
-        Commands::Init { target, name, password } => {
-            ops::do_init(target, name, password)?;
+        Commands::Init { target, name, password, store } => {
+            ops::do_init(target, name, password, store)?;
         }
-        Commands::Snapshot { name, password } => {
+        Commands::Snapshot { name, password, jobs, paranoid } => {
             let pw = password;
-            ops::do_snapshot(&cli.scope, &name, pw)?;
+            ops::do_snapshot(&cli.scope, wait, &name, pw, ScanFlags { jobs, paranoid })?;
         }
-        Commands::Rollback { name, latest } => {
-            ops::do_rollback(&cli.scope, name, latest)?;
+        Commands::Rollback { name, latest, jobs, paranoid } => {
+            ops::do_rollback(&cli.scope, wait, name, latest, ScanFlags { jobs, paranoid })?;
+        }
+        Commands::Status { jobs, paranoid } => {
+            ops::do_status(&cli.scope, wait, ScanFlags { jobs, paranoid })?;
         }
         Commands::Delete { name } => {
-            ops::do_delete(&cli.scope, &name)?;
+            ops::do_delete(&cli.scope, wait, &name)?;
+        }
+        Commands::Gc { dry_run } => {
+            ops::do_gc(&cli.scope, wait, dry_run)?;
+        }
+        Commands::Export { name, output, preserve_mtimes } => {
+            ops::do_export(&cli.scope, wait, &name, &output, preserve_mtimes)?;
+        }
+        Commands::Import { file, name } => {
+            ops::do_import(&cli.scope, wait, &file, name)?;
+        }
+        Commands::Push { names, all, to } => {
+            ops::do_push(&cli.scope, wait, &names, all, to)?;
+        }
+        Commands::Pull { names, all, from, on_conflict } => {
+            ops::do_pull(&cli.scope, wait, &names, all, from, on_conflict)?;
+        }
+        Commands::Verify => {
+            ops::do_verify(&cli.scope, wait)?;
         }
         Commands::Drop => {
-            ops::do_drop(&cli.scope)?;
+            ops::do_drop(&cli.scope, wait)?;
         }
         Commands::List => {
             ops::do_list(&cli.scope)?;
         }
+        Commands::Config { key, value } => {
+            ops::do_config(&cli.scope, wait, key, value)?;
+        }
         Commands::Scopes => {
             ops::do_scopes()?;
         }
+        Commands::Registry { action: RegistryAction::Restore { backup } } => {
+            ops::do_registry_restore(backup)?;
+        }
         Commands::Rename { new_name } => {
-            ops::do_rename(&cli.scope, &new_name)?;
+            ops::do_rename(&cli.scope, wait, &new_name)?;
         }
         Commands::Version => {
             ops::do_version();
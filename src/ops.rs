@@ -1,14 +1,22 @@
 use anyhow::{Result, anyhow};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use rpassword::read_password;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 
-use crate::config::groundhog::{Scope, Snapshot, SnapshotKind};
+use crate::cli::OnConflict;
+use crate::config::groundhog::{GroundHogConfig, Scope, ScopeSettings, Snapshot, SnapshotKind, TreeNode};
 use crate::drivers::selector::select_drivers_for_target;
 use crate::registry;
 use crate::storage;
-use crate::utils::hash::{build_merkle_tree, diff_trees, hash_password, verify_password};
-use crate::utils::io::{copy_selected_files, delete_selected_paths, make_skipper};
+use crate::storage::journal::{self, Intent};
+use crate::storage::lock::{self, LockWait, WorkspaceLock};
+use crate::storage::{archive, backend, gc, remote, verify};
+use crate::storage::objects::{CopyStats, ObjectStore, CHUNKING_THRESHOLD};
+use crate::storage::statcache::StatCache;
+use crate::utils::encoding::display_name;
+use crate::utils::hash::{build_merkle_tree, diff_trees, flatten_tree, hash_password, verify_password, ScanFlags, ScanOptions};
+use crate::utils::io::{copy_tree_files, delete_selected_paths, make_skipper, restore_dir_times, restore_selected_files, set_mode};
 use colored::*;
 use comfy_table::{Attribute, Cell, ContentArrangement, Table, presets::UTF8_FULL};
 
@@ -18,6 +26,7 @@ pub fn do_init(
     target: Option<String>,
     name: Option<String>,
     password: Option<String>,
+    store: Option<String>,
 ) -> Result<()> {
     let target_path = target
         .as_deref()
@@ -30,47 +39,80 @@ pub fn do_init(
 
     // Check for recovery case: scope in registry but no .groundhog
     let all = registry::load_registry()?;
-    if !gh_dir.exists() {
-        if let Some(existing) = all.iter().find(|s| s.target == target_str) {
-            storage::init_at(&target_path, password)?;
-            println!(
-                "{} {}",
-                "i".yellow().bold(),
-                "Recovered missing .groundhog workspace".yellow()
-            );
-
-            match name {
-                Some(new_name) if new_name != existing.name => {
-                    if prompt_confirm(&format!(
-                        "Scope '{}' exists. Keep old name '{}' instead of new name '{}'? [y/N] ",
-                        existing.target, existing.name, new_name
-                    ))? {
-                        println!(
-                            "{} {}",
-                            "✔".green().bold(),
-                            format!("Recovered scope '{}'", existing.name).green()
-                        );
-                    } else {
-                        do_rename(&Some(existing.name.clone()), &new_name)?;
-                    }
+    if !gh_dir.exists()
+        && let Some(existing) = all.iter().find(|s| s.target == target_str)
+    {
+        // The registry remembers an external store, so its snapshots can be reattached.
+        match store.as_deref().or(existing.store.as_deref()).map(Path::new) {
+            Some(dir) if storage::is_store(dir) => {
+                storage::attach_store(&target_path, dir)?;
+                println!(
+                    "{} {}",
+                    "i".yellow().bold(),
+                    format!("Reconnected external store at {}", dir.display()).yellow()
+                );
+            }
+            dir => {
+                let dir = dir.map(|d| storage::prepare_external_store(&target_path, d)).transpose()?;
+                storage::init_at(&target_path, password, dir.as_deref())?;
+                println!(
+                    "{} {}",
+                    "i".yellow().bold(),
+                    "Recovered missing .groundhog workspace".yellow()
+                );
+            }
+        }
+        let store = storage::external_store(&target_path)?.map(|p| p.display().to_string());
+        if store != existing.store {
+            registry::update_registry(|all| {
+                if let Some(s) = all.iter_mut().find(|s| s.name == existing.name) {
+                    s.store = store;
                 }
-                _ => {
+                Ok(())
+            })?;
+        }
+
+        match name {
+            Some(new_name) if new_name != existing.name => {
+                if prompt_confirm(&format!(
+                    "Scope '{}' exists. Keep old name '{}' instead of new name '{}'? [y/N] ",
+                    existing.target, existing.name, new_name
+                ))? {
                     println!(
                         "{} {}",
                         "✔".green().bold(),
                         format!("Recovered scope '{}'", existing.name).green()
                     );
+                } else {
+                    do_rename(&Some(existing.name.clone()), LockWait::default(), &new_name)?;
                 }
             }
-            return Ok(());
+            _ => {
+                println!(
+                    "{} {}",
+                    "✔".green().bold(),
+                    format!("Recovered scope '{}'", existing.name).green()
+                );
+            }
         }
+        return Ok(());
     }
 
     // If .groundhog doesn’t exist, create
     let created_workspace = if gh_dir.exists() {
+        if store.is_some() {
+            return Err(anyhow!(
+                ".groundhog already exists at {}; use `groundhog config store <dir>` to move its data",
+                target_path.display()
+            ));
+        }
         false
     } else {
-        storage::init_at(&target_path, password)?;
+        let dir = store
+            .as_deref()
+            .map(|d| storage::prepare_external_store(&target_path, Path::new(d)))
+            .transpose()?;
+        storage::init_at(&target_path, password, dir.as_deref())?;
         true
     };
 
@@ -82,6 +124,7 @@ pub fn do_init(
         target: target_str,
         kind,
         created_at: chrono::Local::now(),
+        store: storage::external_store(&target_path)?.map(|p| p.display().to_string()),
     };
 
     match registry::register_scope(scope.clone()) {
@@ -119,11 +162,12 @@ pub fn do_init(
 
 pub fn do_snapshot(
     global_scope: &Option<String>,
+    wait: LockWait,
     name: &str,
     password: Option<String>,
+    flags: ScanFlags,
 ) -> Result<()> {
-    let scope = registry::resolve_scope(global_scope)?;
-    let root = std::path::Path::new(&scope.target).to_path_buf();
+    let (scope, root, _lock) = open_scope(global_scope, "snapshot", wait)?;
     let mut config = storage::load_config(&root)?;
 
     if config
@@ -135,7 +179,7 @@ pub fn do_snapshot(
             "{} {}: {}",
             "!".yellow().bold(),
             "Warning".yellow(),
-            format!(
+            format_args!(
                 "snapshot '{}' already exists in scope '{}'; skipping",
                 name, scope.name
             )
@@ -143,65 +187,61 @@ pub fn do_snapshot(
         return Ok(());
     }
 
-    let store_dir = storage::store_dir(&root);
+    let store_dir = storage::store_dir(&root)?;
     let snapshot_dir = storage::snapshot_dir_for(&store_dir, name);
     if snapshot_dir.exists() {
         eprintln!(
             "{} {}: {}",
             "!".yellow().bold(),
             "Warning".yellow(),
-            format!(
+            format_args!(
                 "snapshot directory already exists at '{}'",
                 snapshot_dir.display()
             )
         );
         return Ok(());
     }
+    let directory = relative_path(&snapshot_dir, &storage::data_dir(&root)?)?;
+    journal::begin(
+        &root,
+        &Intent::Snapshot { name: name.to_string(), directory: directory.clone() },
+    )?;
     std::fs::create_dir_all(&snapshot_dir)?;
     let bar = create_progress_bar("Creating snapshot");
 
     // 1) Build current Merkle tree (ignoring .groundhog / .groundhogignore)
-    let skip = make_skipper(&root);
-    let current_tree = build_merkle_tree(&root, "".into(), skip)
-        .map_err(|e| anyhow!("failed to build merkle tree: {}", e))?;
+    let current_tree = scan_scope(&root, &config.settings, flags)?;
 
-    // 2) Find baseline tree (last snapshot in this scope) if any
-    let baseline_tree = config
-        .snapshots
-        .iter()
-        .filter(|s| s.scope == scope.name)
-        .last()
-        .map(|last| storage::load_manifest(&root.join(&last.directory)))
-        .transpose()
-        .unwrap_or(None);
-
-    // 3) Minimal copy based on diff
-    // If no baseline, do a full copy via diff add-all.
-    let (to_copy, _to_delete) = if let Some(base) = &baseline_tree {
-        let d = diff_trees(&current_tree, base);
-        let mut copy_list = d.added.clone();
-        copy_list.extend(d.modified.iter().cloned());
-        (copy_list, d.deleted)
-    } else {
-        // copy all files in current_tree
-        let mut all_files = Vec::new();
-        for (path, (_h, is_dir)) in crate::utils::hash::flatten_tree(&current_tree) {
-            if !is_dir {
-                all_files.push(path);
-            }
-        }
-        (all_files, Vec::new())
+    // 2) Store every file's content in the object store. Objects already present
+    //    (unchanged since an earlier snapshot) are shared rather than copied again,
+    //    so the manifest alone is enough to restore this snapshot in full.
+    //    Large files are chunked, so only their changed regions take new space;
+    //    those unchanged since the last snapshot keep its chunk lists.
+    let store = ObjectStore::open(&root)?.with_compression(config.settings.compression());
+    let has_large_files = flatten_tree(&current_tree).values().any(|n| n.size.unwrap_or(0) >= CHUNKING_THRESHOLD);
+    let previous = config.snapshots.iter().rfind(|s| s.scope == scope.name).filter(|_| has_large_files);
+    let known_chunks: HashMap<String, Vec<String>> = match previous {
+        Some(prev) => storage::load_manifest(&storage::snapshot_path(&root, &prev.directory)?)
+            .map(|tree| {
+                flatten_tree(&tree)
+                    .into_values()
+                    .filter_map(|n| Some((n.content_hash().to_string(), n.chunks.clone()?)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        None => HashMap::new(),
     };
+    let mut current_tree = current_tree;
+    copy_tree_files(&root, &store, &mut current_tree, &known_chunks, &bar)?;
+    store.finish()?;
+    let copies = store.copy_stats();
 
-    // 4) Copy only necessary files into the snapshot dir
-    copy_selected_files(&root, &snapshot_dir, &to_copy, &bar)?;
-
-    // 5) Save manifest in snapshot folder and update meta.json hash_tree
+    // 3) Save manifest in snapshot folder and record the snapshot in meta.json
     storage::save_manifest(&snapshot_dir, &current_tree)?;
     let now = chrono::Local::now();
     config.snapshots.push(Snapshot {
         name: name.to_string(),
-        directory: relative_path(&snapshot_dir, &root)?,
+        directory,
         kind: scope.kind,
         locked: password.as_deref().map(|p| !p.is_empty()).unwrap_or(false),
         created_at: now,
@@ -209,10 +249,9 @@ pub fn do_snapshot(
         password_hash: password.clone().map(|p| hash_password(&p)),
     });
     config.last_updated = chrono::Local::now();
-    config.hash_tree = current_tree;
     storage::save_config(&root, &config)?;
 
-    // 6) (Optional) delegate to drivers for DB etc.
+    // 4) (Optional) delegate to drivers for DB etc.
     let drivers = select_drivers_for_target(&scope.target);
     for driver in drivers {
         bar.set_message(format!("Capturing {}", scope.name));
@@ -221,65 +260,484 @@ pub fn do_snapshot(
         }
         bar.inc(1);
     }
+    journal::complete(&root)?;
 
     bar.finish_with_message("Snapshot created");
     println!(
         "{} {}",
         "✔".green().bold(),
-        format!("Snapshot '{}' created", name).green()
+        format!("Snapshot '{}' created ({})", name, describe_copies(copies)).green()
     );
+
+    if let Some(url) = &config.settings.remote
+        && let Some(snap) = config.snapshots.last()
+    {
+        upload_to_remote(&root, url, snap);
+    }
     Ok(())
 }
 
+/// Upload a freshly created snapshot to the scope's remote. The local snapshot
+/// stands either way, so failing to reach the remote is only a warning.
+fn upload_to_remote(root: &Path, url: &str, snap: &Snapshot) {
+    let bar = create_progress_bar("Uploading to remote");
+    let result = backend::open(url).and_then(|b| {
+        let stats = remote::upload_snapshot(root, snap, b.as_ref())?;
+        Ok((stats, b.location()))
+    });
+    bar.finish_and_clear();
+    match result {
+        Ok((stats, location)) => println!(
+            "{} {}",
+            "✔".green().bold(),
+            format!(
+                "Uploaded to {} ({} new object(s), {})",
+                location,
+                stats.objects,
+                HumanBytes(stats.bytes)
+            )
+            .green()
+        ),
+        Err(e) => eprintln!(
+            "{} {}: {}",
+            "!".yellow().bold(),
+            "Warning".yellow(),
+            format_args!("snapshot kept locally, but uploading it to '{}' failed: {}", url, e)
+        ),
+    }
+}
+
 pub fn do_rollback(
     global_scope: &Option<String>,
+    wait: LockWait,
     name: Option<String>,
     latest: bool,
+    flags: ScanFlags,
 ) -> Result<()> {
-    let scope = registry::resolve_scope(global_scope)?;
-    let root = std::path::Path::new(&scope.target).to_path_buf();
+    let (scope, root, _lock) = open_scope(global_scope, "rollback", wait)?;
+    let mut config = storage::load_config(&root)?;
+    let name = if latest {
+        None
+    } else {
+        Some(name.ok_or_else(|| anyhow!("snapshot name required unless --latest"))?)
+    };
+
+    let local = match &name {
+        None => config.snapshots.iter().rfind(|s| s.scope == scope.name),
+        Some(name) => config.snapshots.iter().find(|s| &s.name == name && s.scope == scope.name),
+    };
+
+    // Snapshots missing locally (say, on a freshly wiped machine) can come from the remote.
+    let mut fetch = None;
+    if let Some(url) = config.settings.remote.clone()
+        && (local.is_none() || latest)
+    {
+        // An unreachable remote only matters when there is no local snapshot to fall back to.
+        match backend::open(&url).and_then(|b| Ok((remote::list_snapshots(b.as_ref())?, b))) {
+            Ok((records, backend)) => {
+                let known: BTreeSet<_> = config.snapshots.iter().map(remote::identity).collect();
+                let mut candidates = records
+                    .into_iter()
+                    .filter(|r| !known.contains(&remote::identity(&r.snapshot)));
+                let record = match &name {
+                    None => candidates
+                        .next_back()
+                        .filter(|r| local.is_none_or(|l| r.snapshot.created_at > l.created_at)),
+                    Some(name) => candidates.rfind(|r| &r.snapshot.name == name),
+                };
+                fetch = record.map(|r| (r, backend));
+            }
+            Err(e) if local.is_some() => eprintln!(
+                "{} {}: {}",
+                "!".yellow().bold(),
+                "Warning".yellow(),
+                format_args!("cannot list remote '{}': {}; using the local snapshot", url, e)
+            ),
+            Err(e) => return Err(e),
+        }
+    }
+
+    let snap = match fetch {
+        Some((record, backend)) => {
+            let bar = create_progress_bar("Fetching snapshot from remote");
+            let (snap, _) = fetch_remote_snapshot(&scope, &root, &mut config, record, backend.as_ref())?;
+            bar.finish_and_clear();
+            snap
+        }
+        None => match (local, &name) {
+            (Some(s), _) => s.clone(),
+            (None, None) => return Err(anyhow!("no snapshots available")),
+            (None, Some(name)) => return Err(anyhow!("snapshot '{}' not found", name)),
+        },
+    };
+
+    let bar = create_progress_bar("Rolling back");
+    journal::begin(
+        &root,
+        &Intent::Rollback { name: snap.name.clone(), directory: snap.directory.clone() },
+    )?;
+    let copies = restore_snapshot(&scope, &root, &snap.directory, &config.settings, flags, &bar)?;
+    journal::complete(&root)?;
+
+    bar.finish_with_message("Rollback complete");
+    println!("{} {}", "✔".green().bold(), format!("Rollback complete ({})", describe_copies(copies)).green());
+    Ok(())
+}
+
+/// Bring a snapshot that only exists in the remote into the local store and
+/// record it in `config` under this scope, as if it had been taken here.
+fn fetch_remote_snapshot(
+    scope: &Scope,
+    root: &Path,
+    config: &mut GroundHogConfig,
+    record: remote::SnapshotRecord,
+    backend: &dyn backend::Backend,
+) -> Result<(Snapshot, remote::TransferStats)> {
+    adopt_snapshot(scope, root, config, record, |store, record, _| {
+        remote::download_snapshot(store, record, backend)
+    })
+}
+
+/// Record a snapshot from another store in `config` under this scope, once
+/// `fill` has put its data into the local object store. `fill` also gets the
+/// new snapshot's folder, which it may use for scratch space.
+fn adopt_snapshot(
+    scope: &Scope,
+    root: &Path,
+    config: &mut GroundHogConfig,
+    record: remote::SnapshotRecord,
+    fill: impl FnOnce(&ObjectStore, &remote::SnapshotRecord, &Path) -> Result<remote::TransferStats>,
+) -> Result<(Snapshot, remote::TransferStats)> {
+    // Another snapshot may already hold the folder name it had where it came from.
+    let store_dir = storage::store_dir(root)?;
+    let id = remote::snapshot_id(&record.snapshot);
+    let snapshot_dir = (1..)
+        .map(|n| if n == 1 { store_dir.join(&id) } else { store_dir.join(format!("{}-{}", id, n)) })
+        .find(|dir| !dir.exists())
+        .expect("some suffix is free");
+    let directory = relative_path(&snapshot_dir, &storage::data_dir(root)?)?;
+    journal::begin(
+        root,
+        &Intent::Snapshot { name: record.snapshot.name.clone(), directory: directory.clone() },
+    )?;
+    let store = ObjectStore::open(root)?.with_compression(config.settings.compression());
+    let stats = match fill(&store, &record, &snapshot_dir) {
+        Ok(stats) => stats,
+        Err(e) => {
+            // A bad source is an expected failure; undo at once rather than at the next run.
+            if snapshot_dir.exists() {
+                std::fs::remove_dir_all(&snapshot_dir)?;
+            }
+            store.remove_temp_files()?;
+            journal::complete(root)?;
+            return Err(e);
+        }
+    };
+    storage::save_manifest(&storage::snapshot_path(root, &directory)?, &record.tree)?;
+
+    let snap = Snapshot { directory, scope: scope.name.clone(), ..record.snapshot };
+    // Keep the list in creation order, which `--latest` relies on.
+    let at = config.snapshots.partition_point(|s| s.created_at <= snap.created_at);
+    config.snapshots.insert(at, snap.clone());
+    config.last_updated = chrono::Local::now();
+    storage::save_config(root, config)?;
+    journal::complete(root)?;
+    Ok((snap, stats))
+}
+
+pub fn do_export(
+    global_scope: &Option<String>,
+    wait: LockWait,
+    name: &str,
+    output: &str,
+    preserve_mtimes: bool,
+) -> Result<()> {
+    let (scope, root, _lock) = open_scope(global_scope, "export", wait)?;
     let config = storage::load_config(&root)?;
+    let snap = config
+        .snapshots
+        .iter()
+        .find(|s| s.name == name && s.scope == scope.name)
+        .ok_or_else(|| anyhow!("snapshot '{}' not found", name))?;
 
-    let snap = if latest {
-        config
-            .snapshots
-            .iter()
-            .filter(|s| s.scope == scope.name)
-            .last()
-            .ok_or_else(|| anyhow!("no snapshots available"))?
+    let bar = create_progress_bar("Exporting");
+    let stats = archive::export_snapshot(&root, snap, Path::new(output), preserve_mtimes)?;
+    bar.finish_and_clear();
+    println!(
+        "{} {}",
+        "✔".green().bold(),
+        format!("Exported '{}' to {} ({} file(s), {})", name, output, stats.files, HumanBytes(stats.bytes)).green()
+    );
+    Ok(())
+}
+
+pub fn do_import(global_scope: &Option<String>, wait: LockWait, file: &str, name: Option<String>) -> Result<()> {
+    let (scope, root, _lock) = open_scope(global_scope, "import", wait)?;
+    let mut config = storage::load_config(&root)?;
+    let path = Path::new(file);
+    let mut record = archive::read_record(path)?;
+    if let Some(name) = name {
+        record.snapshot.name = name;
+    }
+
+    if let Some(existing) = config.snapshots.iter().find(|s| remote::identity(s) == remote::identity(&record.snapshot)) {
+        return Err(anyhow!("'{}' holds snapshot '{}', which is already here", file, existing.name));
+    }
+    if config.snapshots.iter().any(|s| s.name == record.snapshot.name && s.scope == scope.name) {
+        return Err(anyhow!(
+            "scope '{}' already has a snapshot named '{}'; pick another with --name",
+            scope.name,
+            record.snapshot.name
+        ));
+    }
+
+    let bar = create_progress_bar("Importing");
+    let (snap, stats) = adopt_snapshot(&scope, &root, &mut config, record, |store, record, snapshot_dir| {
+        let scratch = snapshot_dir.join("import");
+        std::fs::create_dir_all(&scratch)?;
+        let stats = archive::import_objects(path, store, record, &scratch)?;
+        std::fs::remove_dir_all(&scratch)?;
+        Ok(stats)
+    })?;
+    bar.finish_and_clear();
+    println!(
+        "{} {}",
+        "✔".green().bold(),
+        format!(
+            "Imported '{}' ({} new object(s), {})",
+            snap.name,
+            stats.objects,
+            HumanBytes(stats.bytes)
+        )
+        .green()
+    );
+    Ok(())
+}
+
+pub fn do_push(
+    global_scope: &Option<String>,
+    wait: LockWait,
+    names: &[String],
+    all: bool,
+    to: Option<String>,
+) -> Result<()> {
+    let (scope, root, _lock) = open_scope(global_scope, "push", wait)?;
+    let config = storage::load_config(&root)?;
+    let backend = backend::open(&transfer_location(to, &config, "--to")?)?;
+
+    let own: Vec<&Snapshot> = config.snapshots.iter().filter(|s| s.scope == scope.name).collect();
+    let selected: Vec<&Snapshot> = if all {
+        own
     } else {
-        let name = name.ok_or_else(|| anyhow!("snapshot name required unless --latest"))?;
-        config
-            .snapshots
+        names
             .iter()
-            .find(|s| s.name == name && s.scope == scope.name)
-            .ok_or_else(|| anyhow!("snapshot '{}' not found", name))?
+            .map(|name| {
+                own.iter()
+                    .find(|s| &s.name == name)
+                    .copied()
+                    .ok_or_else(|| anyhow!("snapshot '{}' not found", name))
+            })
+            .collect::<Result<_>>()?
     };
+    if selected.is_empty() {
+        println!("{} {}", "i".yellow().bold(), "No snapshots to push".yellow());
+        return Ok(());
+    }
 
-    let snapshot_path = root.join(&snap.directory);
-    let bar = create_progress_bar("Rolling back");
+    let present: BTreeSet<_> = remote::list_snapshots(backend.as_ref())?
+        .iter()
+        .map(|r| remote::identity(&r.snapshot))
+        .collect();
+    for snap in selected {
+        if present.contains(&remote::identity(snap)) {
+            println!(
+                "{} {}",
+                "i".yellow().bold(),
+                format!("'{}' is already in {}", snap.name, backend.location()).yellow()
+            );
+            continue;
+        }
+        let bar = create_progress_bar(&format!("Pushing '{}'", snap.name));
+        let stats = remote::upload_snapshot(&root, snap, backend.as_ref())?;
+        bar.finish_and_clear();
+        println!(
+            "{} {}",
+            "✔".green().bold(),
+            format!(
+                "Pushed '{}' to {} ({} new object(s), {})",
+                snap.name,
+                backend.location(),
+                stats.objects,
+                HumanBytes(stats.bytes)
+            )
+            .green()
+        );
+    }
+    Ok(())
+}
+
+pub fn do_pull(
+    global_scope: &Option<String>,
+    wait: LockWait,
+    names: &[String],
+    all: bool,
+    from: Option<String>,
+    on_conflict: OnConflict,
+) -> Result<()> {
+    let (scope, root, _lock) = open_scope(global_scope, "pull", wait)?;
+    let mut config = storage::load_config(&root)?;
+    let backend = backend::open(&transfer_location(from, &config, "--from")?)?;
+
+    let known: BTreeSet<_> = config.snapshots.iter().map(remote::identity).collect();
+    let mut records = remote::list_snapshots(backend.as_ref())?;
+    let selected: Vec<remote::SnapshotRecord> = if all {
+        records.retain(|r| !known.contains(&remote::identity(&r.snapshot)));
+        records
+    } else {
+        let mut selected = Vec::new();
+        for name in names {
+            if !records.iter().any(|r| &r.snapshot.name == name) {
+                return Err(anyhow!("snapshot '{}' not found in {}", name, backend.location()));
+            }
+            match records
+                .iter()
+                .rposition(|r| &r.snapshot.name == name && !known.contains(&remote::identity(&r.snapshot)))
+            {
+                Some(at) => selected.push(records.remove(at)),
+                None => println!(
+                    "{} {}",
+                    "i".yellow().bold(),
+                    format!("'{}' is already in this scope", name).yellow()
+                ),
+            }
+        }
+        selected
+    };
+
+    // Settle every name first, so a conflict stops the pull before anything is copied.
+    let mut taken: BTreeSet<String> = config
+        .snapshots
+        .iter()
+        .filter(|s| s.scope == scope.name)
+        .map(|s| s.name.clone())
+        .collect();
+    let mut pulls = Vec::new();
+    for mut record in selected {
+        let name = record.snapshot.name.clone();
+        if taken.contains(&name) {
+            match on_conflict {
+                OnConflict::Error => {
+                    return Err(anyhow!(
+                        "scope '{}' already has a snapshot named '{}'; use --on-conflict skip or rename",
+                        scope.name,
+                        name
+                    ));
+                }
+                OnConflict::Skip => {
+                    println!(
+                        "{} {}",
+                        "i".yellow().bold(),
+                        format!("Skipping '{}': the name is already taken", name).yellow()
+                    );
+                    continue;
+                }
+                OnConflict::Rename => {
+                    let free = (2..)
+                        .map(|n| format!("{}-{}", name, n))
+                        .find(|candidate| !taken.contains(candidate))
+                        .expect("some suffix is free");
+                    record.snapshot.name = free;
+                }
+            }
+        }
+        taken.insert(record.snapshot.name.clone());
+        pulls.push((name, record));
+    }
+    if pulls.is_empty() {
+        println!("{} {}", "i".yellow().bold(), "No snapshots to pull".yellow());
+        return Ok(());
+    }
+
+    for (original, record) in pulls {
+        let bar = create_progress_bar(&format!("Pulling '{}'", original));
+        let (snap, stats) = fetch_remote_snapshot(&scope, &root, &mut config, record, backend.as_ref())?;
+        bar.finish_and_clear();
+        let label = if snap.name == original {
+            format!("'{}'", original)
+        } else {
+            format!("'{}' as '{}'", original, snap.name)
+        };
+        println!(
+            "{} {}",
+            "✔".green().bold(),
+            format!(
+                "Pulled {} from {} ({} new object(s), {})",
+                label,
+                backend.location(),
+                stats.objects,
+                HumanBytes(stats.bytes)
+            )
+            .green()
+        );
+    }
+    Ok(())
+}
+
+/// The store a push or pull talks to: the one given with `flag`, else the scope's remote.
+fn transfer_location(given: Option<String>, config: &GroundHogConfig, flag: &str) -> Result<String> {
+    given.or_else(|| config.settings.remote.clone()).ok_or_else(|| {
+        anyhow!("no remote configured; pass {} <dir|url> or run `groundhog config remote <url>`", flag)
+    })
+}
+
+/// Transform the scope's tree into the snapshot stored in `directory`, touching only
+/// what differs. Safe to re-run after an interruption. `flags` say how the current
+/// tree is scanned. Returns how the restored content was written.
+fn restore_snapshot(
+    scope: &Scope,
+    root: &Path,
+    directory: &str,
+    settings: &ScopeSettings,
+    flags: ScanFlags,
+    bar: &ProgressBar,
+) -> Result<CopyStats> {
+    let snapshot_path = storage::snapshot_path(root, directory)?;
 
     // 1) Load snapshot manifest
     let snap_tree = storage::load_manifest(&snapshot_path)
         .map_err(|e| anyhow!("missing or invalid snapshot manifest: {}", e))?;
 
     // 2) Build current tree to compute minimal changes
-    let skip = crate::utils::io::make_skipper(&root);
-    let current_tree = build_merkle_tree(&root, "".into(), skip)
-        .map_err(|e| anyhow!("failed to build current merkle tree: {}", e))?;
+    let current_tree = scan_scope(root, settings, flags)?;
 
     // 3) Diff (we want to transform current → snapshot)
     let d = diff_trees(&snap_tree, &current_tree);
-    // - For files added/modified in snapshot (relative to current), copy from snapshot to root
+    // - For files added/modified in snapshot (relative to current), restore from the object store
     // - For files deleted in snapshot (relative to current), delete from root
     let mut to_copy = d.added.clone();
     to_copy.extend(d.modified.iter().cloned());
+    // Parents sort before their children, so directories exist before files land in them.
+    to_copy.sort();
 
     // 4) Perform minimal I/O
-    // Copy from snapshot folder (which contains only changed files for that snapshot) *if present*,
-    // otherwise fall back to the full snapshot path.
-    copy_selected_files(&snapshot_path, &root, &to_copy, &bar)?;
-    delete_selected_paths(&root, &d.deleted)?;
+    let store = ObjectStore::open(root)?;
+    delete_selected_paths(root, &d.deleted)?;
+    restore_selected_files(&store, &flatten_tree(&snap_tree), root, &to_copy, settings, bar)?;
+
+    // The root is no entry of its own among those restored: its mode is put back
+    // here, and its times along with those of restored directories and every
+    // directory something was added to or removed from.
+    set_mode(root, snap_tree.mode)?;
+    let mut touched: BTreeSet<String> = BTreeSet::from([String::new()]);
+    for path in to_copy.iter().chain(&d.deleted) {
+        touched.insert(path.clone());
+        let mut parent = path.as_str();
+        while let Some((dir, _)) = parent.rsplit_once('/') {
+            touched.insert(dir.to_string());
+            parent = dir;
+        }
+    }
+    restore_dir_times(root, &snap_tree, &touched)?;
 
     // 5) (Optional) delegate to drivers, e.g., databases
     let drivers = select_drivers_for_target(&scope.target);
@@ -288,26 +746,24 @@ pub fn do_rollback(
             eprintln!("{} {}: {}", "!".yellow().bold(), "Warning".yellow(), err);
         }
     }
+    Ok(store.copy_stats())
+}
 
-    bar.finish_with_message("Rollback complete");
-    println!("{} {}", "✔".green().bold(), "Rollback complete".green());
-    Ok(())
+/// "<n> cloned, <n> copied", for reporting what a snapshot or rollback wrote.
+fn describe_copies(copies: CopyStats) -> String {
+    format!("{} cloned, {} copied", HumanBytes(copies.cloned), HumanBytes(copies.copied))
 }
 
-pub fn do_delete(global_scope: &Option<String>, name: &str) -> Result<()> {
-    let scope = registry::resolve_scope(global_scope)?;
-    let root = std::path::Path::new(&scope.target).to_path_buf();
+pub fn do_delete(global_scope: &Option<String>, wait: LockWait, name: &str) -> Result<()> {
+    let (scope, root, _lock) = open_scope(global_scope, "delete", wait)?;
     let mut config = storage::load_config(&root)?;
 
-    let index = config
+    let snap = config
         .snapshots
         .iter()
-        .position(|s| s.name == name && s.scope == scope.name)
+        .find(|s| s.name == name && s.scope == scope.name)
         .ok_or_else(|| anyhow!("snapshot '{}' not found", name))?;
 
-    let snap = &config.snapshots[index];
-    let snap_path = root.join(&snap.directory);
-
     if let Some(hash) = snap
         .password_hash
         .as_ref()
@@ -331,28 +787,216 @@ pub fn do_delete(global_scope: &Option<String>, name: &str) -> Result<()> {
 
     let bar = create_progress_bar("Deleting snapshot");
     bar.set_message(name.to_string());
+    let directory = snap.directory.clone();
+    journal::begin(
+        &root,
+        &Intent::Delete { name: name.to_string(), directory: directory.clone() },
+    )?;
+    let report = delete_snapshot(&root, &mut config, &directory, &bar)?;
+    journal::complete(&root)?;
+
+    let _ = registry::cleanup_invalid_scopes();
+
+    bar.finish_with_message("Snapshot deleted");
+    println!(
+        "{} {}",
+        "✔".green().bold(),
+        format!(
+            "Deleted snapshot '{}' (reclaimed {})",
+            name,
+            HumanBytes(report.bytes_reclaimed)
+        )
+        .green()
+    );
+    Ok(())
+}
+
+/// Drop the snapshot stored in `directory` from the metadata and the store, then
+/// reclaim data no other snapshot shares. Safe to re-run after an interruption.
+fn delete_snapshot(
+    root: &Path,
+    config: &mut GroundHogConfig,
+    directory: &str,
+    bar: &ProgressBar,
+) -> Result<gc::GcReport> {
+    // The snapshot directory only holds its manifest; file contents may be shared
+    // with other snapshots and are reclaimed by garbage collection below.
+    let snap_path = storage::snapshot_path(root, directory)?;
     if snap_path.exists() {
         std::fs::remove_dir_all(&snap_path)?;
     }
 
-    config.snapshots.remove(index);
+    config.snapshots.retain(|s| s.directory != directory);
     config.last_updated = chrono::Local::now();
-    storage::save_config(&root, &config)?;
+    storage::save_config(root, config)?;
 
-    let _ = registry::cleanup_invalid_scopes();
+    bar.set_message("Collecting unreferenced data");
+    gc::collect_garbage(root, config, false)
+}
 
-    bar.finish_with_message("Snapshot deleted");
+pub fn do_gc(global_scope: &Option<String>, wait: LockWait, dry_run: bool) -> Result<()> {
+    let (_, root, _lock) = open_scope(global_scope, "gc", wait)?;
+    let config = storage::load_config(&root)?;
+
+    let bar = create_progress_bar("Collecting unreferenced data");
+    let report = gc::collect_garbage(&root, &config, dry_run)?;
+    bar.finish_and_clear();
+
+    if dry_run {
+        for (hash, size) in &report.removed {
+            println!("  {} {} ({})", "-".red(), hash, HumanBytes(*size));
+        }
+        println!(
+            "{} {}",
+            "i".yellow().bold(),
+            format!(
+                "Would remove {} unreferenced object(s), reclaiming {} ({} live)",
+                report.removed.len(),
+                HumanBytes(report.bytes_reclaimed),
+                report.live
+            )
+            .yellow()
+        );
+    } else {
+        println!(
+            "{} {}",
+            "✔".green().bold(),
+            format!(
+                "Removed {} unreferenced object(s), reclaimed {} ({} live, {} pack(s) merged)",
+                report.removed.len(),
+                HumanBytes(report.bytes_reclaimed),
+                report.live,
+                report.packs_merged
+            )
+            .green()
+        );
+    }
+    Ok(())
+}
+
+/// Show what changed in the scope since its latest snapshot.
+pub fn do_status(global_scope: &Option<String>, wait: LockWait, flags: ScanFlags) -> Result<()> {
+    let (scope, root, _lock) = open_scope(global_scope, "status", wait)?;
+    let config = storage::load_config(&root)?;
+    // The one `rollback --latest` would restore.
+    let Some(latest) = config.snapshots.iter().rfind(|s| s.scope == scope.name) else {
+        println!("{} {}", "i".yellow().bold(), "No snapshots yet".yellow());
+        return Ok(());
+    };
+    let snap_tree = storage::load_manifest(&storage::snapshot_path(&root, &latest.directory)?)
+        .map_err(|e| anyhow!("missing or invalid snapshot manifest: {}", e))?;
+
+    let current_tree = scan_scope(&root, &config.settings, flags)?;
+    let d = diff_trees(&current_tree, &snap_tree);
+    if d.added.is_empty() && d.modified.is_empty() && d.deleted.is_empty() {
+        println!("{} {}", "✔".green().bold(), format!("No changes since snapshot '{}'", latest.name).green());
+        return Ok(());
+    }
+
+    // A directory changes with whatever changed inside it; only list it when nothing did.
+    let changed: BTreeSet<&str> = d.added.iter().chain(&d.modified).chain(&d.deleted).map(|p| p.as_str()).collect();
+    let inner_change = |dir: &str| {
+        let prefix = format!("{}/", dir);
+        changed.range(prefix.as_str()..).next().is_some_and(|p| p.starts_with(&prefix))
+    };
+    let modified: Vec<&String> = d.modified.iter().filter(|p| !inner_change(p)).collect();
+
+    for path in &d.added {
+        println!("  {} {}", "+".green().bold(), display_name(path).green());
+    }
+    for path in &modified {
+        let shown = if path.is_empty() { ".".to_string() } else { display_name(path) };
+        println!("  {} {}", "~".yellow().bold(), shown.yellow());
+    }
+    for path in &d.deleted {
+        println!("  {} {}", "-".red().bold(), display_name(path).red());
+    }
     println!(
         "{} {}",
-        "✔".green().bold(),
-        format!("Deleted snapshot '{}'", name).green()
+        "i".yellow().bold(),
+        format!(
+            "{} added, {} modified, {} deleted since snapshot '{}'",
+            d.added.len(),
+            modified.len(),
+            d.deleted.len(),
+            latest.name
+        )
+        .yellow()
     );
     Ok(())
 }
 
-pub fn do_drop(global_scope: &Option<String>) -> Result<()> {
+pub fn do_verify(global_scope: &Option<String>, wait: LockWait) -> Result<()> {
+    // Not mutating, but held so a concurrent gc cannot pass for missing data.
+    let (_, root, _lock) = open_scope(global_scope, "verify", wait)?;
+    let config = storage::load_config(&root)?;
+
+    let bar = create_progress_bar("Verifying snapshots");
+    let report = verify::verify_store(&root, &config)?;
+    bar.finish_and_clear();
+
+    if report.snapshots.is_empty() {
+        println!("{} {}", "i".yellow().bold(), "No snapshots found".yellow());
+    } else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Name").add_attribute(Attribute::Bold),
+                Cell::new("Scope").add_attribute(Attribute::Bold),
+                Cell::new("Files").add_attribute(Attribute::Bold),
+                Cell::new("Missing").add_attribute(Attribute::Bold),
+                Cell::new("Corrupt").add_attribute(Attribute::Bold),
+                Cell::new("Status").add_attribute(Attribute::Bold),
+            ]);
+        for h in &report.snapshots {
+            let status = match &h.error {
+                Some(e) => e.red().to_string(),
+                None if h.is_healthy() => "ok".green().to_string(),
+                None => "damaged".red().to_string(),
+            };
+            table.add_row(vec![
+                Cell::new(&h.name),
+                Cell::new(&h.scope),
+                Cell::new(h.files),
+                Cell::new(h.missing.len()),
+                Cell::new(h.corrupt.len()),
+                Cell::new(status),
+            ]);
+        }
+        println!("{}", table);
+    }
+
+    for h in &report.snapshots {
+        for path in &h.missing {
+            eprintln!("{} {}: missing data for '{}'", "✘".red().bold(), h.name, display_name(path));
+        }
+        for path in &h.corrupt {
+            eprintln!("{} {}: corrupt data for '{}'", "✘".red().bold(), h.name, display_name(path));
+        }
+    }
+    for dir in &report.orphaned_dirs {
+        eprintln!(
+            "{} {}: {}",
+            "!".yellow().bold(),
+            "Warning".yellow(),
+            format_args!("store directory '{}' belongs to no snapshot", dir.display())
+        );
+    }
+
+    if !report.is_healthy() {
+        let damaged = report.snapshots.iter().filter(|h| !h.is_healthy()).count();
+        return Err(anyhow!("{} snapshot(s) failed verification", damaged));
+    }
+    println!("{} {}", "✔".green().bold(), "All snapshots verified".green());
+    Ok(())
+}
+
+pub fn do_drop(global_scope: &Option<String>, wait: LockWait) -> Result<()> {
     let scope = registry::resolve_scope(global_scope)?;
     let root = std::path::Path::new(&scope.target).to_path_buf();
+    let _lock = storage::lock_workspace(&root, "drop", wait)?;
     let config = storage::load_config(&root)?;
 
     println!(
@@ -385,8 +1029,13 @@ pub fn do_drop(global_scope: &Option<String>) -> Result<()> {
     let bar = create_progress_bar("Dropping scope");
     bar.set_message(scope.name.clone());
 
-    // Delete .groundhog folder
-    let gh_dir = root.join(".groundhog");
+    // Delete the external store, if any, then the .groundhog folder
+    if let Some(store) = storage::external_store(&root)?
+        && store.exists()
+    {
+        std::fs::remove_dir_all(&store)?;
+    }
+    let gh_dir = storage::groundhog_dir(&root);
     if gh_dir.exists() {
         std::fs::remove_dir_all(&gh_dir)?;
     }
@@ -398,9 +1047,10 @@ pub fn do_drop(global_scope: &Option<String>) -> Result<()> {
     }
 
     // Remove from global registry
-    let mut all = registry::cleanup_invalid_scopes()?;
-    all.retain(|s| s.name != scope.name);
-    registry::save_registry(&all)?;
+    registry::update_registry(|all| {
+        all.retain(|s| s.name != scope.name && Path::new(&s.target).exists());
+        Ok(())
+    })?;
 
     bar.finish_with_message("Scope dropped");
     println!(
@@ -412,44 +1062,236 @@ pub fn do_drop(global_scope: &Option<String>) -> Result<()> {
 }
 
 pub fn do_list(global_scope: &Option<String>) -> Result<()> {
-    let scope = registry::resolve_scope(global_scope)?;
-    let root = std::path::Path::new(&scope.target).to_path_buf();
+    let (_, root) = peek_scope(global_scope, "list")?;
     let config = storage::load_config(&root)?;
 
-    if config.snapshots.is_empty() {
+    // With a remote configured, its snapshots are listed too, fetched on demand.
+    let remote_records = match &config.settings.remote {
+        Some(url) => match backend::open(url).and_then(|b| remote::list_snapshots(b.as_ref())) {
+            Ok(records) => Some(records),
+            Err(e) => {
+                eprintln!(
+                    "{} {}: {}",
+                    "!".yellow().bold(),
+                    "Warning".yellow(),
+                    format_args!("cannot list remote '{}': {}", url, e)
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let remote_ids: BTreeSet<_> = remote_records
+        .iter()
+        .flatten()
+        .map(|r| remote::identity(&r.snapshot))
+        .collect();
+    let local_ids: BTreeSet<_> = config.snapshots.iter().map(remote::identity).collect();
+    let remote_only: Vec<&remote::SnapshotRecord> = remote_records
+        .iter()
+        .flatten()
+        .filter(|r| !local_ids.contains(&remote::identity(&r.snapshot)))
+        .collect();
+
+    if config.snapshots.is_empty() && remote_only.is_empty() {
         println!("{} {}", "i".yellow().bold(), "No snapshots found".yellow());
         return Ok(());
     }
 
+    let mut header = vec![
+        Cell::new("Name").add_attribute(Attribute::Bold),
+        Cell::new("Type").add_attribute(Attribute::Bold),
+        Cell::new("Timestamp").add_attribute(Attribute::Bold),
+        Cell::new("Size").add_attribute(Attribute::Bold),
+        Cell::new("Stored").add_attribute(Attribute::Bold),
+        Cell::new("Locked").add_attribute(Attribute::Bold),
+    ];
+    if remote_records.is_some() {
+        header.push(Cell::new("Location").add_attribute(Attribute::Bold));
+    }
     let mut table = Table::new();
     table
         .load_preset(UTF8_FULL)
         .set_content_arrangement(ContentArrangement::Dynamic)
-        .set_header(vec![
-            Cell::new("Name").add_attribute(Attribute::Bold),
-            Cell::new("Type").add_attribute(Attribute::Bold),
-            Cell::new("Timestamp").add_attribute(Attribute::Bold),
-            Cell::new("Locked").add_attribute(Attribute::Bold),
-        ]);
+        .set_header(header);
 
+    let store = ObjectStore::open(&root)?;
+    let mut rows = Vec::new();
     for s in &config.snapshots {
+        let (logical, stored) = match snapshot_sizes(&root, &store, &s.directory) {
+            Some((l, st)) => (HumanBytes(l).to_string(), HumanBytes(st).to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let location = if remote_ids.contains(&remote::identity(s)) { "local+remote" } else { "local" };
+        rows.push((s, logical, stored, location));
+    }
+    for r in &remote_only {
+        let (logical, _) = tree_sizes(&r.tree);
+        rows.push((&r.snapshot, HumanBytes(logical).to_string(), "-".to_string(), "remote"));
+    }
+    rows.sort_by_key(|(s, ..)| s.created_at);
+
+    for (s, logical, stored, location) in rows {
         let kind = match s.kind {
             SnapshotKind::Filesystem => "filesystem",
             SnapshotKind::Database => "database",
         };
         let ts = s.created_at.format("%Y-%m-%d %H:%M:%S").to_string();
-        table.add_row(vec![
+        let mut row = vec![
             Cell::new(&s.name),
             Cell::new(kind),
             Cell::new(ts),
+            Cell::new(logical),
+            Cell::new(stored),
             Cell::new(if s.locked { "yes" } else { "no" }),
-        ]);
+        ];
+        if remote_records.is_some() {
+            row.push(Cell::new(location));
+        }
+        table.add_row(row);
     }
 
     println!("{}", table);
     Ok(())
 }
 
+/// Logical size of a snapshot's files and the bytes its distinct objects occupy in the store.
+fn snapshot_sizes(root: &Path, store: &ObjectStore, directory: &str) -> Option<(u64, u64)> {
+    let tree = storage::load_manifest(&storage::snapshot_path(root, directory).ok()?).ok()?;
+    let (logical, objects) = tree_sizes(&tree);
+    let stored = objects.iter().filter_map(|h| store.stored_size(h)).sum();
+    Some((logical, stored))
+}
+
+/// Total file size of `tree`, counting hard-linked files once, and the distinct objects it references.
+fn tree_sizes(tree: &TreeNode) -> (u64, BTreeSet<String>) {
+    fn tally(node: &TreeNode, logical: &mut u64, objects: &mut BTreeSet<String>) {
+        if node.is_dir {
+            for child in node.children.iter().flatten() {
+                tally(child, logical, objects);
+            }
+        } else {
+            // Further links to a file take no extra space once restored.
+            if node.hard_link.is_none() {
+                *logical += node.size.unwrap_or(0);
+            }
+            objects.extend(node.object_hashes().into_iter().map(String::from));
+        }
+    }
+
+    let mut logical = 0;
+    let mut objects = BTreeSet::new();
+    tally(tree, &mut logical, &mut objects);
+    (logical, objects)
+}
+
+/// Build the Merkle tree of the scope at `root` (ignoring .groundhog / .groundhogignore).
+/// Files the stat cache vouches for are not read again, unless `flags.paranoid`;
+/// the refreshed cache is saved for the next scan.
+fn scan_scope(root: &Path, settings: &ScopeSettings, flags: ScanFlags) -> Result<TreeNode> {
+    let skip = make_skipper(root);
+    let options = ScanOptions::with_flags(settings, flags);
+    let loaded = StatCache::load(root);
+    let mut cache = loaded.clone();
+    let tree = build_merkle_tree(root, "".into(), options, &mut cache, skip)
+        .map_err(|e| anyhow!("failed to build merkle tree: {}", e))?;
+    if cache != loaded {
+        cache.save(root)?;
+    }
+    Ok(tree)
+}
+
+/// Resolve the target scope, take its workspace lock for `command`, and finish or
+/// undo whatever operation a previous, interrupted run left in its journal, so
+/// every command starts from a consistent state.
+fn open_scope(
+    global_scope: &Option<String>,
+    command: &str,
+    wait: LockWait,
+) -> Result<(Scope, std::path::PathBuf, WorkspaceLock)> {
+    let scope = registry::resolve_scope(global_scope)?;
+    let root = std::path::Path::new(&scope.target).to_path_buf();
+    let lock = storage::lock_workspace(&root, command, wait)?;
+    recover_interrupted(&scope, &root)?;
+    Ok((scope, root, lock))
+}
+
+/// Resolve the target scope for reading only. Recovering an interrupted
+/// operation may rewrite the working tree, so that is left to the next command
+/// that takes the lock; an idle workspace with a pending journal only gets a
+/// warning. While another run holds the lock, its journal belongs to work still
+/// in progress. A workspace written by an older groundhog is upgraded under the
+/// lock first, or not read at all while someone else holds it.
+fn peek_scope(global_scope: &Option<String>, command: &str) -> Result<(Scope, std::path::PathBuf)> {
+    let scope = registry::resolve_scope(global_scope)?;
+    let root = std::path::Path::new(&scope.target).to_path_buf();
+    if storage::needs_upgrade(&root)? {
+        let Some(_lock) = lock::try_acquire(&lock::lock_path(&root)?, command)? else {
+            return Err(anyhow!(
+                "scope '{}' needs upgrading to the current on-disk format, but another groundhog command holds its lock; try again once it finishes",
+                scope.name
+            ));
+        };
+        storage::load_config(&root)?;
+    }
+    if journal::journal_path(&root)?.exists() && lock::try_acquire(&lock::lock_path(&root)?, command)?.is_some() {
+        eprintln!(
+            "{} {}: {}",
+            "!".yellow().bold(),
+            "Warning".yellow(),
+            format_args!(
+                "an interrupted operation is pending in scope '{}'; the next command that takes the workspace lock (e.g. status) will finish it",
+                scope.name
+            )
+        );
+    }
+    Ok((scope, root))
+}
+
+fn recover_interrupted(scope: &Scope, root: &Path) -> Result<()> {
+    // Loaded first: upgrading an older workspace also upgrades its journal.
+    let mut config = storage::load_config(root)?;
+    let Some(intent) = journal::pending(root)? else {
+        return Ok(());
+    };
+    let bar = ProgressBar::hidden();
+
+    match &intent {
+        Intent::Snapshot { name, directory } => {
+            // If meta.json already records the snapshot, only the journal cleanup was lost.
+            if !config.snapshots.iter().any(|s| &s.directory == directory) {
+                let dir = storage::snapshot_path(root, directory)?;
+                if dir.exists() {
+                    std::fs::remove_dir_all(&dir)?;
+                }
+                ObjectStore::open(root)?.remove_temp_files()?;
+                println!(
+                    "{} {}",
+                    "i".yellow().bold(),
+                    format!("Rolled back interrupted snapshot '{}'", name).yellow()
+                );
+            }
+        }
+        Intent::Delete { name, directory } => {
+            delete_snapshot(root, &mut config, directory, &bar)?;
+            println!(
+                "{} {}",
+                "i".yellow().bold(),
+                format!("Finished interrupted delete of snapshot '{}'", name).yellow()
+            );
+        }
+        Intent::Rollback { name, directory } => {
+            restore_snapshot(scope, root, directory, &config.settings, ScanFlags::default(), &bar)?;
+            println!(
+                "{} {}",
+                "i".yellow().bold(),
+                format!("Finished interrupted rollback to snapshot '{}'", name).yellow()
+            );
+        }
+    }
+    journal::complete(root)
+}
+
 fn create_progress_bar(prefix: &str) -> ProgressBar {
     let bar = ProgressBar::new_spinner();
     bar.set_style(
@@ -501,7 +1343,7 @@ pub fn do_scopes() -> Result<()> {
     let mut table = comfy_table::Table::new();
     table
         .load_preset(comfy_table::presets::UTF8_FULL)
-        .set_header(vec!["Name", "Type", "Target", "Created"]);
+        .set_header(vec!["Name", "Type", "Target", "Store", "Created"]);
     for s in &scopes {
         let kind = match s.kind {
             SnapshotKind::Filesystem => "filesystem",
@@ -511,6 +1353,7 @@ pub fn do_scopes() -> Result<()> {
             s.name.clone(),
             kind.to_string(),
             s.target.clone(),
+            s.store.clone().unwrap_or_else(|| "local".to_string()),
             s.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
         ]);
     }
@@ -522,15 +1365,69 @@ pub fn do_scopes() -> Result<()> {
     Ok(())
 }
 
-pub fn do_rename(global_scope: &Option<String>, new_name: &str) -> Result<()> {
+pub fn do_registry_restore(backup: Option<usize>) -> Result<()> {
+    let Some(n) = backup else {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(vec![
+                Cell::new("Backup").add_attribute(Attribute::Bold),
+                Cell::new("Saved").add_attribute(Attribute::Bold),
+                Cell::new("Scopes").add_attribute(Attribute::Bold),
+            ]);
+        let mut found = false;
+        for n in 1..=registry::REGISTRY_BACKUPS {
+            let path = registry::backup_path(n)?;
+            let Ok(md) = std::fs::metadata(&path) else {
+                continue;
+            };
+            found = true;
+            let saved: chrono::DateTime<chrono::Local> = md.modified()?.into();
+            let scopes = match registry::parse_registry(&path) {
+                Ok(list) => list.len().to_string(),
+                Err(_) => "unreadable".red().to_string(),
+            };
+            table.add_row(vec![
+                Cell::new(n),
+                Cell::new(saved.format("%Y-%m-%d %H:%M:%S").to_string()),
+                Cell::new(scopes),
+            ]);
+        }
+        if found {
+            println!("{}", table);
+        } else {
+            println!("{} {}", "i".yellow().bold(), "No registry backups found".yellow());
+        }
+        return Ok(());
+    };
+
+    let path = registry::backup_path(n)?;
+    if !path.exists() {
+        return Err(anyhow!("registry backup {} not found", n));
+    }
+    let _lock = registry::lock_registry()?;
+    let scopes = registry::parse_registry(&path)?;
+    registry::save_registry(&scopes)?;
+    println!(
+        "{} {}",
+        "✔".green().bold(),
+        format!("Restored registry from backup {} ({} scope(s))", n, scopes.len()).green()
+    );
+    Ok(())
+}
+
+pub fn do_rename(global_scope: &Option<String>, wait: LockWait, new_name: &str) -> Result<()> {
     let old_scope_obj = registry::resolve_scope(global_scope)?;
+    let scope_root = std::path::Path::new(&old_scope_obj.target).to_path_buf();
+    let _lock = storage::lock_workspace(&scope_root, "rename", wait)?;
+    let _registry_lock = registry::lock_registry()?;
     // Check global name collision
-    let all = registry::load_registry()?;
+    let mut all = registry::load_registry()?;
     if all.iter().any(|s| s.name == new_name) {
         return Err(anyhow!("scope '{}' already exists", new_name));
     }
     // Update local snapshots' recorded scope name in that workspace
-    let scope_root = std::path::Path::new(&old_scope_obj.target).to_path_buf();
     let mut cfg = storage::load_config(&scope_root)?;
     for snap in cfg
         .snapshots
@@ -541,7 +1438,7 @@ pub fn do_rename(global_scope: &Option<String>, new_name: &str) -> Result<()> {
     }
     storage::save_config(&scope_root, &cfg)?;
     // Update global registry (clean + rename)
-    let mut all = registry::cleanup_invalid_scopes()?;
+    all.retain(|s| Path::new(&s.target).exists());
     if let Some(s) = all.iter_mut().find(|s| s.name == old_scope_obj.name) {
         s.name = new_name.to_string();
     }
@@ -555,6 +1452,118 @@ pub fn do_rename(global_scope: &Option<String>, new_name: &str) -> Result<()> {
     Ok(())
 }
 
+pub fn do_config(
+    global_scope: &Option<String>,
+    wait: LockWait,
+    key: Option<String>,
+    value: Option<String>,
+) -> Result<()> {
+    let (scope, root, _lock) = if value.is_some() {
+        let (scope, root, lock) = open_scope(global_scope, "config", wait)?;
+        (scope, root, Some(lock))
+    } else {
+        let (scope, root) = peek_scope(global_scope, "config")?;
+        (scope, root, None)
+    };
+    let mut config = storage::load_config(&root)?;
+
+    match (key, value) {
+        (None, _) => {
+            println!("{} = {}", "store".bold(), store_setting(&root)?);
+            for (k, v) in config.settings.entries() {
+                println!("{} = {}", k.bold(), v);
+            }
+        }
+        (Some(key), None) if key == "store" => {
+            println!("{}", store_setting(&root)?);
+        }
+        (Some(key), None) => {
+            println!("{}", config.settings.get(&key)?);
+        }
+        (Some(key), Some(value)) if key == "store" => {
+            // "local" moves the data back into .groundhog.
+            let store = match value.as_str() {
+                "local" => None,
+                dir => Some(storage::prepare_external_store(&root, Path::new(dir))?),
+            };
+            let bar = create_progress_bar("Moving snapshot data");
+            storage::relocate(&root, store.as_deref())?;
+            bar.finish_and_clear();
+            registry::update_registry(|all| {
+                if let Some(s) = all.iter_mut().find(|s| s.name == scope.name) {
+                    s.store = store.as_ref().map(|p| p.display().to_string());
+                }
+                Ok(())
+            })?;
+            println!(
+                "{} {}",
+                "✔".green().bold(),
+                format!("store = {}", store_setting(&root)?).green()
+            );
+        }
+        (Some(key), Some(value)) => {
+            config.settings.set(&key, &value)?;
+            config.last_updated = chrono::Local::now();
+            storage::save_config(&root, &config)?;
+            println!(
+                "{} {}",
+                "✔".green().bold(),
+                format!("{} = {}", key, config.settings.get(&key)?).green()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Where the scope's data lives, as shown by `groundhog config`.
+fn store_setting(root: &Path) -> Result<String> {
+    Ok(match storage::external_store(root)? {
+        Some(dir) => dir.display().to_string(),
+        None => "local".to_string(),
+    })
+}
+
 pub fn do_version() {
     println!("{} {}", "groundhog".bold(), "0.1-alpha".cyan());
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use filetime::FileTime;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn rollback_restores_the_root_mode_and_times() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a"), "one").unwrap();
+        let settings = ScopeSettings::default();
+        let directory = "store/20240101000000_s1";
+        fs::create_dir_all(storage::snapshot_path(root, directory).unwrap()).unwrap();
+        fs::set_permissions(root, fs::Permissions::from_mode(0o750)).unwrap();
+        let recorded = FileTime::from_unix_time(1_700_000_000, 0);
+        filetime::set_file_mtime(root, recorded).unwrap();
+        let tree = scan_scope(root, &settings, ScanFlags::default()).unwrap();
+        storage::save_manifest(&storage::snapshot_path(root, directory).unwrap(), &tree).unwrap();
+
+        fs::set_permissions(root, fs::Permissions::from_mode(0o700)).unwrap();
+        filetime::set_file_mtime(root, FileTime::from_unix_time(1_800_000_000, 0)).unwrap();
+        let current = scan_scope(root, &settings, ScanFlags::default()).unwrap();
+        assert_eq!(diff_trees(&current, &tree).modified, vec![String::new()]);
+
+        let scope = Scope {
+            name: "fixture".into(),
+            target: root.display().to_string(),
+            kind: SnapshotKind::Filesystem,
+            created_at: chrono::Local::now(),
+            store: None,
+        };
+        restore_snapshot(&scope, root, directory, &settings, ScanFlags::default(), &ProgressBar::hidden()).unwrap();
+        let md = fs::metadata(root).unwrap();
+        assert_eq!(md.permissions().mode() & 0o7777, 0o750);
+        assert_eq!(FileTime::from_last_modification_time(&md), recorded);
+        assert!(diff_trees(&scan_scope(root, &settings, ScanFlags::default()).unwrap(), &tree).modified.is_empty());
+    }
+}
@@ -1,7 +1,16 @@
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use anyhow::{Result, anyhow};
 use crate::config::groundhog::Scope;
+use crate::storage::lock::{self, LockWait, WorkspaceLock};
+use crate::utils::io::write_atomic;
+
+/// Number of registry versions kept as `registry.json.1` (newest) to `.N`.
+pub const REGISTRY_BACKUPS: usize = 5;
+
+/// Registry updates are quick, so waiting on another run's is always worthwhile.
+const REGISTRY_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
 
 fn config_dir() -> Result<PathBuf> {
     if cfg!(windows) {
@@ -34,72 +43,107 @@ pub fn registry_path() -> Result<PathBuf> {
     let new_path = cfg_dir.join("registry.json");
 
     let old_path = old_registry_path()?;
-    if old_path.exists() && !new_path.exists() {
-        if fs::rename(&old_path, &new_path).is_err() {
-            if let Ok(content) = fs::read(&old_path) {
-                let _ = fs::write(&new_path, &content);
-                let _ = fs::remove_file(&old_path);
-            }
-        }
+    if old_path.exists()
+        && !new_path.exists()
+        && fs::rename(&old_path, &new_path).is_err()
+        && let Ok(content) = fs::read(&old_path)
+    {
+        let _ = fs::write(&new_path, &content);
+        let _ = fs::remove_file(&old_path);
     }
 
     Ok(new_path)
 }
 
+/// Path of the `n`th most recent registry backup.
+pub fn backup_path(n: usize) -> Result<PathBuf> {
+    Ok(registry_path()?.with_extension(format!("json.{}", n)))
+}
+
+/// Read the registry. A file that exists but cannot be parsed is an error rather
+/// than an empty registry, since saving that empty list would forget every scope.
 pub fn load_registry() -> Result<Vec<Scope>> {
     let path = registry_path()?;
     if !path.exists() {
         return Ok(Vec::new());
     }
-    let content = fs::read_to_string(&path)?;
+    parse_registry(&path).map_err(|e| {
+        anyhow!("{}; run `groundhog registry restore` to recover from a backup", e)
+    })
+}
+
+/// Parse a registry file (the live one or a backup).
+pub fn parse_registry(path: &Path) -> Result<Vec<Scope>> {
+    let content = fs::read_to_string(path)?;
     if content.trim().is_empty() {
         return Ok(Vec::new());
     }
-    match serde_json::from_str::<Vec<Scope>>(&content) {
-        Ok(list) => Ok(list),
-        Err(_) => Ok(Vec::new()),
-    }
+    serde_json::from_str::<Vec<Scope>>(&content)
+        .map_err(|e| anyhow!("registry '{}' is corrupt: {}", path.display(), e))
 }
 
+/// Take the registry lock; hold it across a load and the save based on it.
+pub fn lock_registry() -> Result<WorkspaceLock> {
+    let path = registry_path()?.with_extension("lock");
+    lock::acquire(&path, "registry", LockWait::default(), REGISTRY_LOCK_TIMEOUT)
+}
+
+/// Replace the registry with `scopes`. Every version saved is also kept as
+/// `registry.json.1`, shifting older ones up to `REGISTRY_BACKUPS`, so even a
+/// registry damaged after its last save can be restored in full.
+/// Callers must hold the registry lock.
 pub fn save_registry(scopes: &[Scope]) -> Result<()> {
     let path = registry_path()?;
     let json = serde_json::to_string_pretty(scopes)?;
-    let dir = path.parent().ok_or_else(|| anyhow!("invalid registry path"))?;
-    let tmp = dir.join("registry.json.tmp");
-    fs::write(&tmp, json)?;
-    if path.exists() {
-        let _ = fs::remove_file(&path);
+    // Unchanged saves would otherwise push real history out of the backups.
+    if fs::read(&path).is_ok_and(|current| current == json.as_bytes()) {
+        return Ok(());
     }
-    fs::rename(&tmp, &path)?;
-    Ok(())
+    for n in (1..REGISTRY_BACKUPS).rev() {
+        let from = backup_path(n)?;
+        if from.exists() {
+            fs::rename(&from, backup_path(n + 1)?)?;
+        }
+    }
+    write_atomic(&backup_path(1)?, json.as_bytes())?;
+    write_atomic(&path, json.as_bytes())
+}
+
+/// Load, change and save the registry under its lock.
+pub fn update_registry<T>(change: impl FnOnce(&mut Vec<Scope>) -> Result<T>) -> Result<T> {
+    let _lock = lock_registry()?;
+    let mut scopes = load_registry()?;
+    let out = change(&mut scopes)?;
+    save_registry(&scopes)?;
+    Ok(out)
 }
 
 pub fn register_scope(new_scope: Scope) -> Result<()> {
-    let mut scopes = cleanup_invalid_scopes()?;
+    update_registry(|scopes| {
+        retain_valid(scopes);
 
-    if scopes.iter().any(|s| s.name == new_scope.name) {
-        return Err(anyhow!("scope '{}' already exists", new_scope.name));
-    }
-    if scopes.iter().any(|s| s.target == new_scope.target) {
-        return Err(anyhow!("a scope is already registered at target '{}'", new_scope.target));
-    }
+        if scopes.iter().any(|s| s.name == new_scope.name) {
+            return Err(anyhow!("scope '{}' already exists", new_scope.name));
+        }
+        if scopes.iter().any(|s| s.target == new_scope.target) {
+            return Err(anyhow!("a scope is already registered at target '{}'", new_scope.target));
+        }
 
-    scopes.push(new_scope);
-    save_registry(&scopes)
+        scopes.push(new_scope);
+        Ok(())
+    })
 }
 
 pub fn cleanup_invalid_scopes() -> Result<Vec<Scope>> {
-    let scopes = load_registry()?;
-    let mut valid: Vec<Scope> = Vec::new();
-    for s in scopes {
-        let root = Path::new(&s.target);
-        // Keep scope if directory exists (even if .groundhog missing — recovery allowed)
-        if root.exists() {
-            valid.push(s);
-        }
-    }
-    save_registry(&valid)?;
-    Ok(valid)
+    update_registry(|scopes| {
+        retain_valid(scopes);
+        Ok(scopes.clone())
+    })
+}
+
+fn retain_valid(scopes: &mut Vec<Scope>) {
+    // Keep scope if directory exists (even if .groundhog missing — recovery allowed)
+    scopes.retain(|s| Path::new(&s.target).exists());
 }
 
 pub fn resolve_scope(global_scope: &Option<String>) -> Result<Scope> {
@@ -117,10 +161,11 @@ pub fn resolve_scope(global_scope: &Option<String>) -> Result<Scope> {
     while cur.parent().is_some() {
         let gh_path = cur.join(".groundhog");
         if gh_path.exists() {
-            let cfg = crate::storage::load_config(cur)?;
-            if let Some(snap) = cfg.snapshots.first() {
+            // Read as it is: upgrading an older workspace has to wait for its lock.
+            let meta = crate::storage::read_meta_raw(cur)?;
+            if let Some(snap) = meta["snapshots"].get(0) {
                 let all = load_registry()?;
-                if let Some(s) = all.into_iter().find(|x| x.name == snap.scope) {
+                if let Some(s) = all.into_iter().find(|x| snap["scope"] == x.name.as_str()) {
                     return Ok(s);
                 }
             } else {
@@ -0,0 +1,258 @@
+// src/storage/archive.rs
+
+use anyhow::{anyhow, Result};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder, EntryType, Header};
+
+use crate::config::groundhog::{NodeKind, Snapshot, FORMAT_VERSION};
+use crate::storage::objects::ObjectStore;
+use crate::storage::remote::{snapshot_id, SnapshotRecord, TransferStats};
+use crate::storage::{self, migrate};
+use crate::utils::encoding::{decode_name, decode_path, display_name, encode_path};
+use crate::utils::hash::{flatten_tree, sha256_file};
+
+/// First entry of every export: the snapshot's `SnapshotRecord` (metadata and tree).
+const METADATA_ENTRY: &str = "groundhog.json";
+
+/// Directory in the archive the snapshot's files are written under.
+const TREE_DIR: &str = "tree";
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Files and bytes written to an archive.
+#[derive(Debug, Default)]
+pub struct ArchiveStats {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Write `snap` to `out` as a tar archive, zstd-compressed when `out` ends in
+/// `.zst` or `.tzst`. Entries are sorted and carry fixed ownership, the recorded
+/// modes and, unless `preserve_mtimes` asks for the recorded ones, the snapshot's
+/// creation time, so exporting a snapshot twice gives the same bytes.
+pub fn export_snapshot(root: &Path, snap: &Snapshot, out: &Path, preserve_mtimes: bool) -> Result<ArchiveStats> {
+    let tree = storage::load_manifest(&storage::snapshot_path(root, &snap.directory)?)?;
+    let store = ObjectStore::open(root)?;
+    let mtime = snap.created_at.timestamp().max(0) as u64;
+
+    // Written beside `out` and renamed at the end, so a failed export leaves no partial archive.
+    let name = out.file_name().ok_or_else(|| anyhow!("invalid output path '{}'", out.display()))?;
+    let tmp = out.with_file_name(format!(".{}.tmp", name.to_string_lossy()));
+    // Only the folder's name is kept, not where the exporting scope keeps it.
+    let snapshot = Snapshot { directory: snapshot_id(snap), ..snap.clone() };
+    let record = SnapshotRecord { format_version: FORMAT_VERSION, snapshot, tree };
+
+    let result = (|| {
+        let file = BufWriter::new(File::create(&tmp)?);
+        let (file, stats) = if is_zstd_name(out) {
+            let (encoder, stats) = write_tar(zstd::Encoder::new(file, 0)?, &record, &store, mtime, preserve_mtimes)?;
+            (encoder.finish()?, stats)
+        } else {
+            write_tar(file, &record, &store, mtime, preserve_mtimes)?
+        };
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&tmp, out)?;
+        Ok(stats)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn write_tar<W: Write>(
+    writer: W,
+    record: &SnapshotRecord,
+    store: &ObjectStore,
+    mtime: u64,
+    preserve_mtimes: bool,
+) -> Result<(W, ArchiveStats)> {
+    let mut builder = Builder::new(writer);
+    let mut stats = ArchiveStats::default();
+
+    let json = serde_json::to_vec_pretty(record)?;
+    append(&mut builder, Path::new(METADATA_ENTRY), EntryType::Regular, json.len() as u64, None, mtime, &json[..])?;
+    append(&mut builder, &tree_entry("", true), EntryType::Directory, 0, None, mtime, io::empty())?;
+
+    // Sorted by path, so every directory comes right before its contents.
+    for (path, node) in flatten_tree(&record.tree) {
+        let entry = tree_entry(&path, node.is_dir);
+        let mtime = match node.mtime {
+            Some(ns) if preserve_mtimes => ns.div_euclid(1_000_000_000).max(0) as u64,
+            _ => mtime,
+        };
+        if node.is_dir {
+            append(&mut builder, &entry, EntryType::Directory, 0, node.mode, mtime, io::empty())?;
+            continue;
+        }
+        // Special files have no content; they travel in the record and come back on rollback.
+        if node.hash.is_empty() || node.kind.is_special() {
+            continue;
+        }
+        if let Some(target) = node.target.as_deref().filter(|_| node.kind == NodeKind::Symlink) {
+            let mut header = header(EntryType::Symlink, 0, Some(0o777), mtime);
+            builder.append_link(&mut header, &entry, decode_name(target))?;
+            continue;
+        }
+        // The group's first file sorts before its other links, so it is already in the archive.
+        if let Some(leader) = &node.hard_link {
+            let mut header = header(EntryType::Link, 0, node.mode, mtime);
+            builder.append_link(&mut header, &entry, tree_entry(leader, false))?;
+            continue;
+        }
+        let mut content: Box<dyn Read> = Box::new(io::empty());
+        for hash in node.object_hashes() {
+            content = Box::new(content.chain(store.open_object(hash)?));
+        }
+        // Manifests from before sizes were recorded need the content measured first.
+        let size = match node.size {
+            Some(size) => size,
+            None => {
+                let mut data = Vec::new();
+                content.read_to_end(&mut data)?;
+                let size = data.len() as u64;
+                content = Box::new(io::Cursor::new(data));
+                size
+            }
+        };
+        append(&mut builder, &entry, EntryType::Regular, size, node.mode, mtime, content)?;
+        stats.files += 1;
+        stats.bytes += size;
+    }
+    Ok((builder.into_inner()?, stats))
+}
+
+/// Read the snapshot record an exported archive starts with.
+pub fn read_record(path: &Path) -> Result<SnapshotRecord> {
+    read_record_from(path).map_err(|e| anyhow!("cannot import '{}': {}", path.display(), e))
+}
+
+fn read_record_from(path: &Path) -> Result<SnapshotRecord> {
+    let mut archive = open_archive(path)?;
+    let mut entries = archive.entries()?;
+    let mut first = entries
+        .next()
+        .ok_or_else(|| anyhow!("the archive is empty"))?
+        .map_err(|e| anyhow!("not a tar archive ({})", e))?;
+    if first.path()?.to_string_lossy() != METADATA_ENTRY {
+        return Err(anyhow!("not a groundhog export (no {})", METADATA_ENTRY));
+    }
+    let mut json = Vec::new();
+    first.read_to_end(&mut json)?;
+    let value: serde_json::Value = serde_json::from_slice(&json)
+        .map_err(|e| anyhow!("invalid {}: {}", METADATA_ENTRY, e))?;
+    let version = migrate::format_version_of(&value);
+    migrate::check_supported(version, path)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Store the file contents of the archive at `path` in `store`, checking each
+/// against `record`'s tree. `scratch` is an empty directory to stage files in.
+pub fn import_objects(
+    path: &Path,
+    store: &ObjectStore,
+    record: &SnapshotRecord,
+    scratch: &Path,
+) -> Result<TransferStats> {
+    let files = flatten_tree(&record.tree);
+    let mut pending: std::collections::BTreeSet<&str> = files
+        .iter()
+        .filter(|(_, n)| !n.is_dir && n.kind.is_file() && n.hard_link.is_none() && !n.hash.is_empty())
+        .map(|(p, _)| p.as_str())
+        .collect();
+    let mut stats = TransferStats::default();
+
+    let mut archive = open_archive(path)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+        let name = entry.path()?.into_owned();
+        let Ok(rel) = name.strip_prefix(TREE_DIR) else {
+            continue;
+        };
+        let staged = scratch.join(rel.file_name().unwrap_or_default());
+        let rel = encode_path(rel);
+        let Some(node) = files.get(&rel).filter(|n| !n.is_dir && n.kind.is_file()) else {
+            return Err(anyhow!("'{}' in the archive is not part of its snapshot", display_name(&rel)));
+        };
+
+        // Staged under the file's own name, which the compression heuristics look at.
+        io::copy(&mut entry, &mut File::create(&staged)?)?;
+        if sha256_file(&staged)? != node.content_hash() {
+            return Err(anyhow!("'{}' in the archive does not match its manifest", display_name(&rel)));
+        }
+        if node.chunks.is_some() {
+            let chunks = store.insert_chunked(&staged, node.content_hash())?;
+            if node.chunks.as_ref() != Some(&chunks) {
+                return Err(anyhow!("'{}' was split into different chunks than its manifest lists", display_name(&rel)));
+            }
+            stats.objects += chunks.len();
+        } else if store.insert_file(&staged, node.content_hash())? {
+            stats.objects += 1;
+        }
+        stats.bytes += fs::metadata(&staged)?.len();
+        fs::remove_file(&staged)?;
+        pending.remove(rel.as_str());
+    }
+    if let Some(missing) = pending.first() {
+        return Err(anyhow!("'{}' is missing from the archive", display_name(missing)));
+    }
+    store.finish()?;
+    Ok(stats)
+}
+
+fn open_archive(path: &Path) -> Result<Archive<Box<dyn Read>>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; 4];
+    let compressed = file.read_exact(&mut magic).is_ok() && magic == ZSTD_MAGIC;
+    let file = BufReader::new(File::open(path)?);
+    let reader: Box<dyn Read> = if compressed { Box::new(zstd::Decoder::new(file)?) } else { Box::new(file) };
+    Ok(Archive::new(reader))
+}
+
+fn is_zstd_name(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(".zst") || name.ends_with(".tzst")
+}
+
+/// Archive path of the manifest path `path` (encoded, "" for the tree itself).
+fn tree_entry(path: &str, is_dir: bool) -> PathBuf {
+    let mut entry = OsString::from(TREE_DIR);
+    if !path.is_empty() {
+        entry.push("/");
+        entry.push(decode_path(path));
+    }
+    if is_dir {
+        entry.push("/");
+    }
+    PathBuf::from(entry)
+}
+
+fn append<W: Write, R: Read>(
+    builder: &mut Builder<W>,
+    path: &Path,
+    kind: EntryType,
+    size: u64,
+    mode: Option<u32>,
+    mtime: u64,
+    data: R,
+) -> Result<()> {
+    builder.append_data(&mut header(kind, size, mode, mtime), path, data)?;
+    Ok(())
+}
+
+fn header(kind: EntryType, size: u64, mode: Option<u32>, mtime: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_size(size);
+    header.set_mode(mode.unwrap_or(if kind == EntryType::Directory { 0o755 } else { 0o644 }));
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(mtime);
+    header
+}
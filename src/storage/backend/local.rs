@@ -0,0 +1,133 @@
+// src/storage/backend/local.rs
+
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+use super::Backend;
+use crate::utils::io::{write_atomic, write_atomic_from};
+
+/// Blobs as plain files below a directory, e.g. on a mounted network share.
+pub struct LocalBackend {
+    dir: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        key.split('/').fold(self.dir.clone(), |p, part| p.join(part))
+    }
+}
+
+impl Backend for LocalBackend {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        write_atomic(&self.path(key), data)
+    }
+
+    fn open(&self, key: &str) -> Result<Option<Box<dyn Read>>> {
+        match fs::File::open(self.path(key)) {
+            Ok(file) => Ok(Some(Box::new(file))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put_stream(&self, key: &str, data: &mut dyn Read, len: u64, _sha256: &str) -> Result<()> {
+        let path = self.path(key);
+        let written = write_atomic_from(&path, data)?;
+        if written != len {
+            fs::remove_file(&path)?;
+            return Err(anyhow!("'{}' came out {} bytes long instead of {}", key, written, len));
+        }
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        // Walk only the directory the prefix names, not the whole tree.
+        let (dir, _) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+        let mut keys = Vec::new();
+        collect_keys(&self.path(dir), dir, &mut keys)?;
+        keys.retain(|k| k.starts_with(prefix));
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn location(&self) -> String {
+        self.dir.display().to_string()
+    }
+}
+
+fn collect_keys(dir: &Path, key: &str, out: &mut Vec<String>) -> Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Temp files of interrupted writes are not blobs.
+        if name.ends_with(".tmp") {
+            continue;
+        }
+        let child = if key.is_empty() { name } else { format!("{}/{}", key, name) };
+        if entry.file_type()?.is_dir() {
+            collect_keys(&entry.path(), &child, out)?;
+        } else {
+            out.push(child);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = LocalBackend::new(dir.path());
+        assert_eq!(backend.get("snapshots/a.json").unwrap(), None);
+        assert!(backend.list("snapshots/").unwrap().is_empty());
+
+        backend.put("snapshots/a.json", b"first").unwrap();
+        backend.put("snapshots/b.json", b"second").unwrap();
+        backend.put("objects/ab/abcd", b"object").unwrap();
+        backend.put("snapshots/a.json", b"replaced").unwrap();
+        fs::write(dir.path().join("snapshots/.c.json.tmp"), b"partial").unwrap();
+
+        assert_eq!(backend.get("snapshots/a.json").unwrap().as_deref(), Some(&b"replaced"[..]));
+        assert_eq!(backend.get("objects/ab/abcd").unwrap().as_deref(), Some(&b"object"[..]));
+        let mut streamed = Vec::new();
+        backend.open("snapshots/b.json").unwrap().unwrap().read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, b"second");
+        assert!(backend.open("snapshots/c.json").unwrap().is_none());
+        assert_eq!(backend.list("snapshots/").unwrap(), ["snapshots/a.json", "snapshots/b.json"]);
+        assert_eq!(backend.list("snapshots/b").unwrap(), ["snapshots/b.json"]);
+        assert_eq!(backend.list("").unwrap(), ["objects/ab/abcd", "snapshots/a.json", "snapshots/b.json"]);
+    }
+
+    #[test]
+    fn streams_blobs_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = LocalBackend::new(dir.path());
+        let data = vec![7u8; 3 << 20];
+        backend.put_stream("objects/big", &mut &data[..], data.len() as u64, "").unwrap();
+        assert_eq!(backend.get("objects/big").unwrap(), Some(data));
+        assert!(backend.put_stream("objects/short", &mut &b"abc"[..], 4, "").is_err());
+        assert_eq!(backend.get("objects/short").unwrap(), None);
+    }
+}
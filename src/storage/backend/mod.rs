@@ -0,0 +1,40 @@
+// src/storage/backend/mod.rs
+
+use anyhow::{anyhow, Result};
+use std::io::Read;
+use std::path::Path;
+
+pub mod local;
+pub mod s3;
+
+/// A place snapshots can be copied to and fetched back from, beyond the
+/// scope's own store: a flat map from `/`-separated keys to blobs.
+pub trait Backend {
+    /// The blob stored under `key`, or `None` if there is none.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Store `data` under `key`, replacing any previous blob.
+    fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+    /// The blob stored under `key` as a stream, for blobs too large to hold in
+    /// memory, or `None` if there is none.
+    fn open(&self, key: &str) -> Result<Option<Box<dyn Read>>>;
+    /// Store the `len` bytes `data` yields under `key` without holding them in
+    /// memory; `sha256` is their hex hash, which some backends sign.
+    fn put_stream(&self, key: &str, data: &mut dyn Read, len: u64, sha256: &str) -> Result<()>;
+    /// Every key starting with `prefix`.
+    fn list(&self, prefix: &str) -> Result<Vec<String>>;
+    /// Where the backend points, for messages.
+    fn location(&self) -> String;
+}
+
+/// Open the backend a remote URL names: `s3://bucket[/prefix]` for an
+/// S3-compatible bucket, or a directory (optionally as `file://<path>`).
+pub fn open(url: &str) -> Result<Box<dyn Backend>> {
+    if let Some(rest) = url.strip_prefix("s3://") {
+        return Ok(Box::new(s3::S3Backend::from_env(rest)?));
+    }
+    let dir = url.strip_prefix("file://").unwrap_or(url);
+    if dir.is_empty() || dir.contains("://") {
+        return Err(anyhow!("unsupported remote '{}'; use s3://bucket[/prefix] or a directory", url));
+    }
+    Ok(Box::new(local::LocalBackend::new(Path::new(dir))))
+}
@@ -0,0 +1,436 @@
+// src/storage/backend/s3.rs
+
+use anyhow::{anyhow, Result};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use ureq::http::Response;
+use ureq::{Agent, Body, SendBody};
+
+use super::Backend;
+
+/// Upper bound on a response body read into memory (listings, snapshot records,
+/// error messages); objects are streamed instead.
+const MAX_BODY: u64 = 1 << 30;
+
+/// What a request sends: bytes in memory, or `len` bytes streamed from `data`,
+/// whose hash is known up front.
+enum Payload<'a> {
+    Bytes(&'a [u8]),
+    Stream { data: &'a mut dyn Read, len: u64, sha256: &'a str },
+}
+
+/// An S3-compatible bucket (AWS, MinIO, ...), signed with AWS Signature V4.
+///
+/// Configured from the usual AWS environment variables: `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY`, optionally `AWS_SESSION_TOKEN`, `AWS_REGION` (or
+/// `AWS_DEFAULT_REGION`, default `us-east-1`) and `AWS_ENDPOINT_URL` (or
+/// `AWS_ENDPOINT_URL_S3`) for services other than AWS itself.
+pub struct S3Backend {
+    agent: Agent,
+    /// Scheme and authority requests go to, e.g. `http://127.0.0.1:9000`.
+    endpoint: String,
+    host: String,
+    bucket: String,
+    /// Key prefix inside the bucket: empty, or ending in `/`.
+    prefix: String,
+    /// Address the bucket as `/<bucket>/<key>` rather than as a subdomain;
+    /// custom endpoints such as MinIO expect this.
+    path_style: bool,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Backend {
+    /// `location` is the part of an `s3://` URL after the scheme: `bucket[/prefix]`.
+    pub fn from_env(location: &str) -> Result<Self> {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("s3 remote is missing a bucket name"));
+        }
+        let prefix = prefix.trim_matches('/');
+        let prefix = if prefix.is_empty() { String::new() } else { format!("{}/", prefix) };
+
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let access_key = var("AWS_ACCESS_KEY_ID").ok_or_else(|| anyhow!("AWS_ACCESS_KEY_ID is not set"))?;
+        let secret_key =
+            var("AWS_SECRET_ACCESS_KEY").ok_or_else(|| anyhow!("AWS_SECRET_ACCESS_KEY is not set"))?;
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+
+        let (endpoint, path_style) = match var("AWS_ENDPOINT_URL_S3").or_else(|| var("AWS_ENDPOINT_URL")) {
+            Some(url) => (url.trim_end_matches('/').to_string(), true),
+            None => (format!("https://{}.s3.{}.amazonaws.com", bucket, region), false),
+        };
+        let host = endpoint
+            .split_once("://")
+            .map(|(_, rest)| rest)
+            .filter(|rest| !rest.is_empty() && !rest.contains('/'))
+            .ok_or_else(|| anyhow!("invalid S3 endpoint '{}'; expected scheme://host[:port]", endpoint))?
+            .to_string();
+
+        let config = Agent::config_builder().http_status_as_error(false).build();
+        Ok(Self {
+            agent: config.into(),
+            endpoint,
+            host,
+            bucket: bucket.to_string(),
+            prefix,
+            path_style,
+            region,
+            access_key,
+            secret_key,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+
+    /// Request path for `key`, or for the bucket itself.
+    fn path(&self, key: Option<&str>) -> String {
+        let bucket = if self.path_style { format!("/{}", self.bucket) } else { String::new() };
+        match key {
+            Some(key) => format!("{}/{}", bucket, uri_encode(&format!("{}{}", self.prefix, key), false)),
+            None if bucket.is_empty() => "/".to_string(),
+            None => bucket,
+        }
+    }
+
+    /// Send a signed request; returns the status and body.
+    fn request(&self, method: &str, key: Option<&str>, query: &[(&str, &str)], body: &[u8]) -> Result<(u16, Vec<u8>)> {
+        let mut response = self.send(method, key, query, Payload::Bytes(body))?;
+        let status = response.status().as_u16();
+        let data = response.body_mut().with_config().limit(MAX_BODY).read_to_vec()?;
+        Ok((status, data))
+    }
+
+    /// Send a signed request, leaving its response body unread.
+    fn send(&self, method: &str, key: Option<&str>, query: &[(&str, &str)], payload: Payload) -> Result<Response<Body>> {
+        let path = self.path(key);
+        let query = canonical_query(query);
+
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = match &payload {
+            Payload::Bytes(body) => hex::encode(Sha256::digest(body)),
+            Payload::Stream { sha256, .. } => sha256.to_string(),
+        };
+
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let (canonical_request, signed_headers) = canonical_request(method, &path, &query, &headers, &payload_hash);
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            credential_scope(&amz_date, &self.region),
+            signed_headers,
+            signature(&self.secret_key, &amz_date, &self.region, &canonical_request)
+        );
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.endpoint, path)
+        } else {
+            format!("{}{}?{}", self.endpoint, path, query)
+        };
+        let result = match (method, payload) {
+            ("PUT", Payload::Bytes(body)) => self.sign(self.agent.put(&url), &headers, &authorization).send(body),
+            // A known length keeps ureq from falling back to chunked encoding, which S3 refuses.
+            ("PUT", Payload::Stream { data, len, .. }) => self
+                .sign(self.agent.put(&url), &headers, &authorization)
+                .header("content-length", len)
+                .send(SendBody::from_reader(data)),
+            _ => self.sign(self.agent.get(&url), &headers, &authorization).call(),
+        };
+        result.map_err(|e| anyhow!("S3 request to {} failed: {}", self.endpoint, e))
+    }
+
+    /// Attach the signed headers (ureq sets `host` itself).
+    fn sign<B>(
+        &self,
+        request: ureq::RequestBuilder<B>,
+        headers: &[(&str, String)],
+        authorization: &str,
+    ) -> ureq::RequestBuilder<B> {
+        headers
+            .iter()
+            .filter(|(k, _)| *k != "host")
+            .fold(request, |r, (k, v)| r.header(*k, v))
+            .header("authorization", authorization)
+    }
+
+    fn failure(&self, method: &str, key: &str, status: u16, body: &[u8]) -> anyhow::Error {
+        let body = String::from_utf8_lossy(body);
+        let message = xml_values(&body, "Message").into_iter().next().unwrap_or_default();
+        anyhow!("S3 {} '{}' in bucket '{}' failed: HTTP {} {}", method, key, self.bucket, status, message)
+    }
+}
+
+impl Backend for S3Backend {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.request("GET", Some(key), &[], &[])? {
+            (200, data) => Ok(Some(data)),
+            (404, _) => Ok(None),
+            (status, body) => Err(self.failure("GET", key, status, &body)),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        match self.request("PUT", Some(key), &[], data)? {
+            (200, _) => Ok(()),
+            (status, body) => Err(self.failure("PUT", key, status, &body)),
+        }
+    }
+
+    fn open(&self, key: &str) -> Result<Option<Box<dyn Read>>> {
+        let mut response = self.send("GET", Some(key), &[], Payload::Bytes(&[]))?;
+        match response.status().as_u16() {
+            200 => Ok(Some(Box::new(response.into_body().into_reader()))),
+            404 => Ok(None),
+            status => {
+                let body = response.body_mut().with_config().limit(MAX_BODY).read_to_vec()?;
+                Err(self.failure("GET", key, status, &body))
+            }
+        }
+    }
+
+    fn put_stream(&self, key: &str, data: &mut dyn Read, len: u64, sha256: &str) -> Result<()> {
+        let mut response = self.send("PUT", Some(key), &[], Payload::Stream { data, len, sha256 })?;
+        match response.status().as_u16() {
+            200 => Ok(()),
+            status => {
+                let body = response.body_mut().with_config().limit(MAX_BODY).read_to_vec()?;
+                Err(self.failure("PUT", key, status, &body))
+            }
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let full_prefix = format!("{}{}", self.prefix, prefix);
+        collect_listing(&self.prefix, |token| {
+            let mut query = vec![("list-type", "2"), ("prefix", full_prefix.as_str())];
+            if let Some(t) = token {
+                query.push(("continuation-token", t));
+            }
+            let (status, body) = self.request("GET", None, &query, &[])?;
+            if status != 200 {
+                return Err(self.failure("LIST", prefix, status, &body));
+            }
+            Ok(String::from_utf8_lossy(&body).into_owned())
+        })
+    }
+
+    fn location(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+}
+
+/// Keys, relative to the backend's `prefix`, of every page of a ListObjectsV2
+/// listing; `page` fetches the page a continuation token names (the first for `None`).
+fn collect_listing(prefix: &str, mut page: impl FnMut(Option<&str>) -> Result<String>) -> Result<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        let xml = page(token.as_deref())?;
+        for key in xml_values(&xml, "Key") {
+            if let Some(k) = key.strip_prefix(prefix) {
+                keys.push(k.to_string());
+            }
+        }
+        let truncated = xml_values(&xml, "IsTruncated").first().is_some_and(|v| v == "true");
+        token = xml_values(&xml, "NextContinuationToken").into_iter().next();
+        if !truncated || token.is_none() {
+            break;
+        }
+    }
+    keys.sort();
+    Ok(keys)
+}
+
+/// Query parameters, encoded and sorted as SigV4 requires.
+fn canonical_query(query: &[(&str, &str)]) -> String {
+    let mut params: Vec<(String, String)> =
+        query.iter().map(|(k, v)| (uri_encode(k, true), uri_encode(v, true))).collect();
+    params.sort();
+    params.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&")
+}
+
+/// The SigV4 canonical request and its signed-headers list. `headers` must have
+/// lowercase names.
+fn canonical_request(
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(&str, String)],
+    payload_hash: &str,
+) -> (String, String) {
+    let mut headers = headers.to_vec();
+    headers.sort();
+    let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+    let signed_headers = headers.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(";");
+    let request = format!("{}\n{}\n{}\n{}\n{}\n{}", method, path, query, canonical_headers, signed_headers, payload_hash);
+    (request, signed_headers)
+}
+
+/// `<date>/<region>/s3/aws4_request` for an `x-amz-date` timestamp.
+fn credential_scope(amz_date: &str, region: &str) -> String {
+    format!("{}/{}/s3/aws4_request", &amz_date[..8], region)
+}
+
+/// Hex SigV4 signature of a canonical request made at `amz_date`.
+fn signature(secret_key: &str, amz_date: &str, region: &str, canonical_request: &str) -> String {
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        credential_scope(amz_date, region),
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = hmac(format!("AWS4{}", secret_key).as_bytes(), &amz_date.as_bytes()[..8]);
+    for part in [region, "s3", "aws4_request"] {
+        key = hmac(&key, part.as_bytes());
+    }
+    hex::encode(hmac(&key, string_to_sign.as_bytes()))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode as SigV4 requires: everything but unreserved characters,
+/// and `/` too unless encoding a path.
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Text of every `<tag>...</tag>` element in an S3 XML response.
+fn xml_values(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut out = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        rest = &rest[start + open.len()..];
+        let Some(end) = rest.find(&close) else {
+            break;
+        };
+        out.push(
+            rest[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        );
+        rest = &rest[end + close.len()..];
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from the Amazon S3 API reference, "Signature Calculations for the
+    // Authorization Header: Transferring Payload in a Single Chunk".
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY";
+    const AMZ_DATE: &str = "20130524T000000Z";
+    const EMPTY_HASH: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn headers(extra: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        let mut headers = vec![
+            ("x-amz-date", AMZ_DATE.to_string()),
+            ("host", "examplebucket.s3.amazonaws.com".to_string()),
+            ("x-amz-content-sha256", EMPTY_HASH.to_string()),
+        ];
+        headers.extend(extra.iter().map(|(k, v)| (*k, v.to_string())));
+        headers
+    }
+
+    #[test]
+    fn signs_get_object_example() {
+        let (request, signed) = canonical_request("GET", "/test.txt", "", &headers(&[("range", "bytes=0-9")]), EMPTY_HASH);
+        assert_eq!(
+            request,
+            "GET\n/test.txt\n\nhost:examplebucket.s3.amazonaws.com\nrange:bytes=0-9\n\
+             x-amz-content-sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\n\
+             x-amz-date:20130524T000000Z\n\nhost;range;x-amz-content-sha256;x-amz-date\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(signed, "host;range;x-amz-content-sha256;x-amz-date");
+        assert_eq!(
+            signature(SECRET_KEY, AMZ_DATE, "us-east-1", &request),
+            "f0e8bdb87c964420e857bd35b5d6ed310bd44f0170aba48dd91039c6036bdb41"
+        );
+    }
+
+    #[test]
+    fn signs_list_objects_example() {
+        let query = canonical_query(&[("prefix", "J"), ("max-keys", "2")]);
+        assert_eq!(query, "max-keys=2&prefix=J");
+        let (request, _) = canonical_request("GET", "/", &query, &headers(&[]), EMPTY_HASH);
+        assert_eq!(
+            signature(SECRET_KEY, AMZ_DATE, "us-east-1", &request),
+            "34b48302e7b5fa45bde8084f4b7868a86f0a534bc59db6670ed5711ef69dc6f7"
+        );
+    }
+
+    #[test]
+    fn encodes_keys_for_the_path() {
+        assert_eq!(uri_encode("snapshots/a b+c~.json", false), "snapshots/a%20b%2Bc~.json");
+        assert_eq!(uri_encode("a/b", true), "a%2Fb");
+    }
+
+    #[test]
+    fn parses_list_objects_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Name>bucket</Name><Prefix>team/</Prefix><KeyCount>2</KeyCount><IsTruncated>false</IsTruncated>
+  <Contents><Key>team/objects/ab</Key><Size>3</Size></Contents>
+  <Contents><Key>team/snapshots/R&amp;D.json</Key><Size>5</Size></Contents>
+</ListBucketResult>"#;
+        assert_eq!(xml_values(xml, "Key"), ["team/objects/ab", "team/snapshots/R&D.json"]);
+        let keys = collect_listing("team/", |_| Ok(xml.to_string())).unwrap();
+        assert_eq!(keys, ["objects/ab", "snapshots/R&D.json"]);
+    }
+
+    #[test]
+    fn follows_continuation_tokens() {
+        let page = |keys: &[&str], next: Option<&str>| {
+            let contents: String = keys.iter().map(|k| format!("<Contents><Key>{}</Key></Contents>", k)).collect();
+            let next = next.map(|t| format!("<NextContinuationToken>{}</NextContinuationToken>", t)).unwrap_or_default();
+            format!(
+                "<ListBucketResult><IsTruncated>{}</IsTruncated>{}{}</ListBucketResult>",
+                !next.is_empty(),
+                contents,
+                next
+            )
+        };
+        let mut tokens = Vec::new();
+        let keys = collect_listing("", |token| {
+            tokens.push(token.map(str::to_string));
+            Ok(match token {
+                None => page(&["c", "a"], Some("1/2")),
+                Some("1/2") => page(&["b"], Some("2/2")),
+                _ => page(&["d"], None),
+            })
+        })
+        .unwrap();
+        assert_eq!(keys, ["a", "b", "c", "d"]);
+        assert_eq!(tokens, [None, Some("1/2".to_string()), Some("2/2".to_string())]);
+    }
+}
@@ -0,0 +1,74 @@
+// src/storage/gc.rs
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::config::groundhog::{GroundHogConfig, TreeNode};
+use crate::storage::{self, objects::ObjectStore};
+
+/// Outcome of a garbage-collection pass over a scope's object store.
+#[derive(Debug, Default)]
+pub struct GcReport {
+    /// Objects that are (or, for a dry run, would be) removed, as (hash, size).
+    pub removed: Vec<(String, u64)>,
+    /// Objects still referenced by at least one snapshot.
+    pub live: usize,
+    pub bytes_reclaimed: u64,
+    /// Pack files merged into fresh packs by the repack step.
+    pub packs_merged: usize,
+}
+
+/// Count how many snapshot manifests reference each object.
+///
+/// Every snapshot recorded in `config` is walked, whatever scope name it carries,
+/// since all snapshots in a workspace share one object store. A snapshot whose
+/// manifest cannot be read is a hard error: guessing would risk deleting data it needs.
+pub fn reference_counts(root: &Path, config: &GroundHogConfig) -> Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+    let data = storage::data_dir(root)?;
+    for snap in &config.snapshots {
+        let tree = storage::load_manifest(&data.join(&snap.directory)).map_err(|e| {
+            anyhow!("cannot read manifest for snapshot '{}': {}", snap.name, e)
+        })?;
+        count_refs(&tree, &mut counts);
+    }
+    Ok(counts)
+}
+
+fn count_refs(node: &TreeNode, counts: &mut BTreeMap<String, usize>) {
+    if node.is_dir {
+        for child in node.children.iter().flatten() {
+            count_refs(child, counts);
+        }
+    } else if !node.hash.is_empty() {
+        for hash in node.object_hashes() {
+            *counts.entry(hash.to_string()).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Remove every object no snapshot references, then repack so dead packed
+/// entries are dropped and small packs are merged. With `dry_run`, nothing is
+/// deleted or rewritten and the report lists what would have been removed.
+pub fn collect_garbage(root: &Path, config: &GroundHogConfig, dry_run: bool) -> Result<GcReport> {
+    let counts = reference_counts(root, config)?;
+    let store = ObjectStore::open(root)?;
+
+    let mut report = GcReport::default();
+    for (hash, size) in store.list()? {
+        if counts.contains_key(&hash) {
+            report.live += 1;
+            continue;
+        }
+        if !dry_run {
+            store.remove(&hash)?;
+        }
+        report.bytes_reclaimed += size;
+        report.removed.push((hash, size));
+    }
+    if !dry_run {
+        report.packs_merged = store.repack(|hash| counts.contains_key(hash))?;
+    }
+    Ok(report)
+}
@@ -0,0 +1,60 @@
+// src/storage/journal.rs
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+use crate::utils::io::write_atomic;
+
+/// An operation that has started changing the workspace but not yet finished.
+///
+/// The intent is written before the first change and cleared after the last,
+/// so finding one on startup means the previous run was interrupted midway.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Intent {
+    /// Creating snapshot `name` in `directory`. Recovery rolls it back unless
+    /// `meta.json` already records it, in which case it had completed.
+    Snapshot { name: String, directory: String },
+    /// Deleting the snapshot stored in `directory`. Recovery finishes the job.
+    Delete { name: String, directory: String },
+    /// Restoring the scope to the snapshot stored in `directory`. Recovery
+    /// replays the rollback, since a half-restored tree matches no snapshot.
+    Rollback { name: String, directory: String },
+}
+
+pub fn journal_path(root: &Path) -> Result<PathBuf> {
+    Ok(storage::data_dir(root)?.join("journal.json"))
+}
+
+/// Record `intent` before touching anything. Only one operation may be in flight.
+pub fn begin(root: &Path, intent: &Intent) -> Result<()> {
+    let p = journal_path(root)?;
+    if p.exists() {
+        return Err(anyhow!("an interrupted operation is still pending in {}", p.display()));
+    }
+    write_atomic(&p, serde_json::to_string_pretty(intent)?.as_bytes())
+}
+
+/// Mark the in-flight operation as finished.
+pub fn complete(root: &Path) -> Result<()> {
+    let p = journal_path(root)?;
+    if p.exists() {
+        fs::remove_file(p)?;
+    }
+    Ok(())
+}
+
+/// The operation a previous run left unfinished, if any.
+pub fn pending(root: &Path) -> Result<Option<Intent>> {
+    let p = journal_path(root)?;
+    if !p.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&p)?;
+    let intent = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("unreadable journal {}: {}", p.display(), e))?;
+    Ok(Some(intent))
+}
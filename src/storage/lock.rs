@@ -0,0 +1,186 @@
+// src/storage/lock.rs
+
+use anyhow::{anyhow, Result};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::storage;
+use crate::utils::io::write_atomic;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long to wait for a workspace lock held by another groundhog run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LockWait {
+    /// Fail immediately instead of waiting.
+    pub no_wait: bool,
+    /// Overrides the scope's `lock-timeout` setting.
+    pub timeout: Option<Duration>,
+}
+
+/// Who holds a workspace lock; stored as the lock file's content.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Holder {
+    pid: u32,
+    host: String,
+    command: String,
+    acquired_at: chrono::DateTime<chrono::Local>,
+}
+
+/// Exclusive, advisory lock on a workspace (or the registry), released when dropped.
+pub struct WorkspaceLock {
+    path: PathBuf,
+}
+
+impl Drop for WorkspaceLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn lock_path(root: &Path) -> Result<PathBuf> {
+    Ok(storage::data_dir(root)?.join("lock"))
+}
+
+/// Take the lock file at `path` for `command`, waiting up to `timeout` (unless
+/// `wait.no_wait`) for another run to release it. A lock left behind by a
+/// process that no longer exists on this host is broken and taken over.
+pub fn acquire(path: &Path, command: &str, wait: LockWait, timeout: Duration) -> Result<WorkspaceLock> {
+    let path = path.to_path_buf();
+    let deadline = Instant::now() + wait.timeout.unwrap_or(timeout);
+    let mut announced = false;
+    loop {
+        if let Some(lock) = try_acquire(&path, command)? {
+            return Ok(lock);
+        }
+        let Some(holder) = read_holder(&path) else {
+            if !path.exists() {
+                // Released between our attempt and the read; try again right away.
+                continue;
+            }
+            if wait.no_wait || Instant::now() >= deadline {
+                return Err(anyhow!(
+                    "lock '{}' is unreadable; remove it if no groundhog is running",
+                    path.display()
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        };
+        if holder.host == hostname() && !process_alive(holder.pid) {
+            break_stale(&path, &holder)?;
+            eprintln!(
+                "{} {}: {}",
+                "!".yellow().bold(),
+                "Warning".yellow(),
+                format_args!("removed stale lock left by '{}' (pid {}, no longer running)", holder.command, holder.pid)
+            );
+            continue;
+        }
+        if wait.no_wait || Instant::now() >= deadline {
+            return Err(anyhow!(
+                "'{}' is held by '{}' (pid {} on {}, since {}); {}",
+                path.display(),
+                holder.command,
+                holder.pid,
+                holder.host,
+                holder.acquired_at.format("%Y-%m-%d %H:%M:%S"),
+                if wait.no_wait { "not waiting (--no-wait)" } else { "timed out waiting for it" }
+            ));
+        }
+        if !announced {
+            eprintln!(
+                "{} {}",
+                "i".yellow().bold(),
+                format!("Waiting for '{}' (pid {}) to release '{}'...", holder.command, holder.pid, path.display()).yellow()
+            );
+            announced = true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Take the lock only if it is free right now.
+pub fn try_acquire(path: &Path, command: &str) -> Result<Option<WorkspaceLock>> {
+    let dir = path.parent().ok_or_else(|| anyhow!("invalid lock path"))?;
+    if !dir.is_dir() {
+        return Err(anyhow!("cannot lock {}: no such directory", dir.display()));
+    }
+    let holder = Holder {
+        pid: std::process::id(),
+        host: hostname(),
+        command: command.to_string(),
+        acquired_at: chrono::Local::now(),
+    };
+    // Write the holder aside, then link it into place: creating the link is the
+    // atomic step, and the lock file never exists without its content.
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    write_atomic(&tmp, serde_json::to_string_pretty(&holder)?.as_bytes())?;
+    let linked = fs::hard_link(&tmp, path);
+    fs::remove_file(&tmp)?;
+    match linked {
+        Ok(()) => Ok(Some(WorkspaceLock { path: path.to_path_buf() })),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+        Err(e) => Err(anyhow!("failed to create lock '{}': {}", path.display(), e)),
+    }
+}
+
+fn read_holder(path: &Path) -> Option<Holder> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Remove a lock whose holder is gone. The file is first moved aside so that,
+/// should another run have replaced it meanwhile, its fresh lock can be put back.
+fn break_stale(path: &Path, stale: &Holder) -> Result<()> {
+    let aside = path.with_extension(format!("{}.stale", std::process::id()));
+    match fs::rename(path, &aside) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    }
+    if read_holder(&aside).as_ref() != Some(stale) {
+        let _ = fs::hard_link(&aside, path);
+    }
+    fs::remove_file(&aside)?;
+    Ok(())
+}
+
+fn hostname() -> String {
+    #[cfg(unix)]
+    {
+        let mut buf = [0u8; 256];
+        let ok = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } == 0;
+        if ok {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            return String::from_utf8_lossy(&buf[..len]).into_owned();
+        }
+    }
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+/// Whether `pid` names a running process on this host. Where that cannot be
+/// determined the process is assumed alive, so a lock is never broken wrongly.
+fn process_alive(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(pid) = libc::pid_t::try_from(pid) else {
+            return true;
+        };
+        // Signal 0 only checks existence; EPERM means it exists under another user.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+        std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
@@ -0,0 +1,441 @@
+// src/storage/migrate.rs
+
+use anyhow::{anyhow, Result};
+use colored::*;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::groundhog::{TreeNode, FORMAT_VERSION};
+use crate::storage::{self, journal, objects::ObjectStore};
+use crate::utils::hash::flatten_tree;
+use crate::utils::io::write_atomic;
+
+/// A migration upgrades the raw `meta.json` (and whatever else on disk it
+/// needs to touch) from one format version to the next.
+type Migration = fn(&Path, &mut Value) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a workspace from format version `n` to `n + 1`,
+/// so its length must always equal `FORMAT_VERSION`.
+const MIGRATIONS: &[Migration] = &[v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8, v8_to_v9, v9_to_v10, v10_to_v11];
+
+/// Format version recorded in a raw `meta.json` or manifest. Files written
+/// before versioning was introduced carry none and count as version 0.
+pub fn format_version_of(value: &Value) -> u32 {
+    value
+        .get("format_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// Refuse files written by a newer groundhog than this one.
+pub fn check_supported(version: u32, path: &Path) -> Result<()> {
+    check_supported_at(version, &format!("'{}'", path.display()))
+}
+
+/// `check_supported` for data described by `location` rather than a local path.
+/// Snapshot records that travel between stores (exports, remotes) are never
+/// migrated: older ones only lack what newer formats added, so they read as they are.
+pub fn check_supported_at(version: u32, location: &str) -> Result<()> {
+    if version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "{} uses on-disk format v{}, but this groundhog only understands up to v{}; please upgrade groundhog",
+            location,
+            version,
+            FORMAT_VERSION
+        ));
+    }
+    Ok(())
+}
+
+/// Bring the workspace at `root` up to `FORMAT_VERSION`, returning the upgraded
+/// `meta.json` value. The original metadata (meta.json and every manifest) is
+/// copied to `.groundhog/backups/v<old>-<timestamp>/` before anything changes.
+pub fn migrate_workspace(root: &Path, mut meta: Value) -> Result<Value> {
+    let from = format_version_of(&meta);
+    let meta_path = storage::meta_path(root)?;
+    check_supported(from, &meta_path)?;
+    if from == FORMAT_VERSION {
+        return Ok(meta);
+    }
+
+    let backup = backup_metadata(root, &meta, from)?;
+    for version in from..FORMAT_VERSION {
+        MIGRATIONS[version as usize](root, &mut meta).map_err(|e| {
+            anyhow!(
+                "failed to migrate workspace from format v{} to v{}: {} (original metadata kept at {})",
+                version,
+                version + 1,
+                e,
+                backup.display()
+            )
+        })?;
+        meta["format_version"] = json!(version + 1);
+    }
+    write_atomic(&meta_path, serde_json::to_string_pretty(&meta)?.as_bytes())?;
+
+    eprintln!(
+        "{} {}",
+        "i".yellow().bold(),
+        format!(
+            "Upgraded workspace format v{} → v{} (backup at {})",
+            from,
+            FORMAT_VERSION,
+            backup.display()
+        )
+        .yellow()
+    );
+    Ok(meta)
+}
+
+/// Each snapshot's recorded `directory` and where it is on disk. Before v2,
+/// directories were relative to the scope root rather than the data dir.
+fn snapshot_dirs(root: &Path, meta: &Value) -> Result<Vec<(String, PathBuf)>> {
+    let base = if format_version_of(meta) < 2 { root.to_path_buf() } else { storage::data_dir(root)? };
+    Ok(meta
+        .get("snapshots")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.get("directory").and_then(Value::as_str))
+        .map(|d| (d.to_string(), base.join(d)))
+        .collect())
+}
+
+fn backup_metadata(root: &Path, meta: &Value, version: u32) -> Result<PathBuf> {
+    let backup = storage::data_dir(root)?
+        .join("backups")
+        .join(format!("v{}-{}", version, chrono::Local::now().format("%Y%m%d%H%M%S")));
+    fs::create_dir_all(&backup)?;
+    fs::copy(storage::meta_path(root)?, backup.join("meta.json"))?;
+    for (rel, dir) in snapshot_dirs(root, meta)? {
+        let manifest = storage::manifest_path(&dir);
+        if manifest.is_file() {
+            let dest = storage::manifest_path(&backup.join(rel));
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&manifest, dest)?;
+        }
+    }
+    Ok(backup)
+}
+
+/// v0 → v1: manifests gain a `{ format_version, tree }` envelope, and file
+/// copies that pre-object-store snapshots kept inside their own directory are
+/// moved into the object store so those snapshots restore in full.
+fn v0_to_v1(root: &Path, meta: &mut Value) -> Result<()> {
+    let store = ObjectStore::open(root)?;
+    for (_, dir) in snapshot_dirs(root, meta)? {
+        let manifest = storage::manifest_path(&dir);
+        if !manifest.is_file() {
+            continue;
+        }
+        let raw: Value = serde_json::from_str(&fs::read_to_string(&manifest)?)?;
+        // Tolerate manifests already wrapped by an interrupted earlier attempt.
+        let mut raw_tree = match raw.get("tree") {
+            Some(t) => t.clone(),
+            None => raw,
+        };
+        record_sizes(&mut raw_tree, &dir);
+        let tree: TreeNode = serde_json::from_value(raw_tree.clone())?;
+
+        for (path, node) in flatten_tree(&tree) {
+            let legacy = dir.join(&path);
+            if node.is_dir || !legacy.is_file() {
+                continue;
+            }
+            // A copy that no longer matches its recorded hash is left where it is.
+            if store.contains(&node.hash) || store.insert_file(&legacy, &node.hash).is_ok() {
+                fs::remove_file(&legacy)?;
+            }
+        }
+        store.finish()?;
+        prune_empty_dirs(&dir)?;
+
+        let wrapped = json!({ "format_version": 1, "tree": raw_tree });
+        write_atomic(&manifest, serde_json::to_string_pretty(&wrapped)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// v1 → v2: snapshot directories become relative to the data dir rather than
+/// the scope root (`store/<dir>` instead of `.groundhog/store/<dir>`), so the
+/// data can live in an external store. Manifests are restamped to match, and a
+/// pending journal entry is rewritten the same way.
+fn v1_to_v2(root: &Path, meta: &mut Value) -> Result<()> {
+    for (_, dir) in snapshot_dirs(root, meta)? {
+        let manifest = storage::manifest_path(&dir);
+        if !manifest.is_file() {
+            continue;
+        }
+        let mut raw: Value = serde_json::from_str(&fs::read_to_string(&manifest)?)?;
+        raw["format_version"] = json!(2);
+        write_atomic(&manifest, serde_json::to_string_pretty(&raw)?.as_bytes())?;
+    }
+    for snap in meta
+        .get_mut("snapshots")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        let Some(dir) = snap.get("directory").and_then(Value::as_str) else {
+            continue;
+        };
+        if let Ok(rel) = Path::new(dir).strip_prefix(".groundhog") {
+            snap["directory"] = json!(rel.to_string_lossy());
+        }
+    }
+
+    let journal = journal::journal_path(root)?;
+    if journal.is_file() {
+        let mut intent: Value = serde_json::from_str(&fs::read_to_string(&journal)?)?;
+        if let Some(dir) = intent.get("directory").and_then(Value::as_str)
+            && let Ok(rel) = Path::new(dir).strip_prefix(".groundhog")
+        {
+            intent["directory"] = json!(rel.to_string_lossy());
+            write_atomic(&journal, serde_json::to_string_pretty(&intent)?.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// v2 → v3: manifests may now record each entry's Unix permission bits, which older
+/// groundhogs would silently drop on rollback; existing manifests only need restamping.
+fn v2_to_v3(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 3)
+}
+
+/// v3 → v4: manifests may now record modification and access times, which older
+/// groundhogs would silently drop on rollback; existing manifests only need restamping.
+fn v3_to_v4(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 4)
+}
+
+/// v4 → v5: manifests may now hold symlink nodes, which older groundhogs would
+/// restore as missing files; existing manifests only need restamping.
+fn v4_to_v5(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 5)
+}
+
+/// v5 → v6: manifests may now link files into hard-link groups, which older groundhogs
+/// would restore as unrelated copies; existing manifests only need restamping.
+fn v5_to_v6(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 6)
+}
+
+/// v6 → v7: a file's `hash` may now cover its extended attributes, with the object
+/// key moved to `content_hash`; existing manifests only need restamping.
+fn v6_to_v7(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 7)
+}
+
+/// v7 → v8: manifests may now record owners, which older groundhogs would silently
+/// drop on rollback; existing manifests only need restamping.
+fn v7_to_v8(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 8)
+}
+
+/// v8 → v9: manifests may now hold FIFOs, sockets and device nodes, which older
+/// groundhogs cannot read; existing manifests only need restamping.
+fn v8_to_v9(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 9)
+}
+
+/// v9 → v10: names that are not valid UTF-8 are now recorded with their stray bytes
+/// escaped after a NUL, which older groundhogs would try to create verbatim;
+/// existing manifests only need restamping.
+fn v9_to_v10(root: &Path, meta: &mut Value) -> Result<()> {
+    restamp_manifests(root, meta, 10)
+}
+
+/// v10 → v11: `meta.json` no longer keeps a copy of the last snapshot's tree (its
+/// manifest has it), which made every command parse the whole tree; the
+/// stat cache took over avoiding re-hashes.
+fn v10_to_v11(root: &Path, meta: &mut Value) -> Result<()> {
+    if let Some(meta) = meta.as_object_mut() {
+        meta.remove("hash_tree");
+    }
+    restamp_manifests(root, meta, 11)
+}
+
+fn restamp_manifests(root: &Path, meta: &Value, version: u32) -> Result<()> {
+    for (_, dir) in snapshot_dirs(root, meta)? {
+        let manifest = storage::manifest_path(&dir);
+        if !manifest.is_file() {
+            continue;
+        }
+        let mut raw: Value = serde_json::from_str(&fs::read_to_string(&manifest)?)?;
+        raw["format_version"] = json!(version);
+        write_atomic(&manifest, serde_json::to_string_pretty(&raw)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Fill in the `size` of file nodes from the legacy copies next to the manifest.
+fn record_sizes(node: &mut Value, path: &Path) {
+    let name = node.get("name").and_then(Value::as_str).unwrap_or("").to_string();
+    let path = path.join(name);
+    if let Some(children) = node.get_mut("children").and_then(Value::as_array_mut) {
+        for child in children {
+            record_sizes(child, &path);
+        }
+    } else if node.get("size").is_none()
+        && let Ok(md) = fs::metadata(&path)
+        && md.is_file()
+    {
+        node["size"] = json!(md.len());
+    }
+}
+
+/// Remove empty subdirectories below `dir` (but not `dir` itself).
+fn prune_empty_dirs(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let p = entry?.path();
+        if p.is_dir() {
+            prune_empty_dirs(&p)?;
+            let _ = fs::remove_dir(&p);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hash::sha256_bytes;
+
+    const LEGACY_DIR: &str = ".groundhog/store/20240101000000_s1";
+
+    fn write_json(path: &Path, value: &Value) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, serde_json::to_string_pretty(value).unwrap()).unwrap();
+    }
+
+    fn read_json(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    /// `meta.json` with one snapshot stored in `directory`, as written by the
+    /// given format version (`None` before versioning).
+    fn meta_fixture(version: Option<u32>, directory: &str) -> Value {
+        let mut meta = json!({
+            "date_created": "2024-01-01T00:00:00Z",
+            "last_updated": "2024-01-01T00:00:00Z",
+            "password_hash": null,
+            "snapshots": [{
+                "name": "s1",
+                "directory": directory,
+                "kind": "Filesystem",
+                "locked": false,
+                "created_at": "2024-01-01T00:00:00Z",
+                "scope": "fixture",
+                "password_hash": null
+            }]
+        });
+        if let Some(version) = version {
+            meta["format_version"] = json!(version);
+        }
+        meta
+    }
+
+    /// A manifest tree holding `a`, `c` and `d/b`.
+    fn tree_fixture() -> Value {
+        json!({
+            "name": "", "hash": "root", "is_dir": true,
+            "children": [
+                { "name": "a", "hash": sha256_bytes(b"one\n"), "is_dir": false },
+                { "name": "c", "hash": sha256_bytes(b"three\n"), "is_dir": false },
+                { "name": "d", "hash": "d", "is_dir": true, "children": [
+                    { "name": "b", "hash": sha256_bytes(b"two\n"), "is_dir": false }
+                ] }
+            ]
+        })
+    }
+
+    fn backups(root: &Path) -> Vec<PathBuf> {
+        match fs::read_dir(storage::groundhog_dir(root).join("backups")) {
+            Ok(entries) => entries.map(|e| e.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn upgrades_v0_workspaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let meta = meta_fixture(None, LEGACY_DIR);
+        write_json(&storage::groundhog_dir(root).join("meta.json"), &meta);
+        let legacy = root.join(LEGACY_DIR);
+        write_json(&storage::manifest_path(&legacy), &tree_fixture());
+        fs::write(legacy.join("a"), "one\n").unwrap();
+        // A copy that no longer matches its recorded hash.
+        fs::write(legacy.join("c"), "changed\n").unwrap();
+        fs::create_dir(legacy.join("d")).unwrap();
+        fs::write(legacy.join("d/b"), "two\n").unwrap();
+
+        let cfg = storage::load_config(root).unwrap();
+        assert_eq!(cfg.format_version, FORMAT_VERSION);
+        assert_eq!(cfg.snapshots[0].directory, "store/20240101000000_s1");
+        assert_eq!(format_version_of(&read_json(&storage::meta_path(root).unwrap())), FORMAT_VERSION);
+
+        // Legacy copies moved into the store; the mismatched one stays put.
+        let store = ObjectStore::open(root).unwrap();
+        for data in [&b"one\n"[..], b"two\n"] {
+            let mut content = Vec::new();
+            store.open_object(&sha256_bytes(data)).unwrap().read_to_end(&mut content).unwrap();
+            assert_eq!(content, data);
+        }
+        assert!(!legacy.join("a").exists());
+        assert!(!legacy.join("d").exists());
+        assert_eq!(fs::read_to_string(legacy.join("c")).unwrap(), "changed\n");
+        assert!(!store.contains(&sha256_bytes(b"three\n")));
+
+        let manifest = read_json(&storage::manifest_path(&legacy));
+        assert_eq!(format_version_of(&manifest), FORMAT_VERSION);
+        assert_eq!(manifest["tree"]["children"][0]["size"], 4);
+        storage::load_manifest(&legacy).unwrap();
+
+        // The original metadata is kept as it was.
+        let backups = backups(root);
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].file_name().unwrap().to_string_lossy().starts_with("v0-"));
+        assert_eq!(read_json(&backups[0].join("meta.json")), meta);
+        assert_eq!(read_json(&storage::manifest_path(&backups[0].join(LEGACY_DIR))), tree_fixture());
+    }
+
+    #[test]
+    fn upgrades_v1_directories_and_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write_json(&storage::groundhog_dir(root).join("meta.json"), &meta_fixture(Some(1), LEGACY_DIR));
+        let snapshot = root.join(LEGACY_DIR);
+        write_json(&storage::manifest_path(&snapshot), &json!({ "format_version": 1, "tree": tree_fixture() }));
+        let journal = journal::journal_path(root).unwrap();
+        write_json(&journal, &json!({ "op": "rollback", "name": "s1", "directory": LEGACY_DIR }));
+
+        let cfg = storage::load_config(root).unwrap();
+        assert_eq!(cfg.snapshots[0].directory, "store/20240101000000_s1");
+        assert_eq!(format_version_of(&read_json(&storage::manifest_path(&snapshot))), FORMAT_VERSION);
+        assert_eq!(read_json(&journal)["directory"], "store/20240101000000_s1");
+        assert!(matches!(journal::pending(root).unwrap(), Some(journal::Intent::Rollback { .. })));
+        assert_eq!(backups(root).len(), 1);
+    }
+
+    #[test]
+    fn refuses_newer_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let meta = meta_fixture(Some(FORMAT_VERSION + 1), "store/20240101000000_s1");
+        write_json(&storage::groundhog_dir(root).join("meta.json"), &meta);
+
+        let err = storage::load_config(root).err().unwrap().to_string();
+        assert!(err.contains("please upgrade groundhog"), "{}", err);
+        assert_eq!(read_json(&storage::meta_path(root).unwrap()), meta);
+        assert!(backups(root).is_empty());
+
+        let err = check_supported_at(FORMAT_VERSION + 1, "snapshot 's1' in s3://bucket").err().unwrap().to_string();
+        assert!(err.starts_with("snapshot 's1' in s3://bucket uses on-disk format"), "{}", err);
+        check_supported_at(0, "snapshot 's1' in s3://bucket").unwrap();
+    }
+}
@@ -1,18 +1,55 @@
 // src/storage/mod.rs
 
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::config::groundhog::{GroundHogConfig, TreeNode};
+use crate::config::groundhog::{GroundHogConfig, Manifest, ScopeSettings, TreeNode, FORMAT_VERSION};
+use crate::utils::io::{copy_dir_contents, write_atomic};
 
-pub fn init_at(target: &Path, password: Option<String>) -> Result<()> {
+pub mod archive;
+pub mod backend;
+pub mod gc;
+pub mod journal;
+pub mod lock;
+pub mod migrate;
+pub mod objects;
+pub mod pack;
+pub mod remote;
+pub mod statcache;
+pub mod verify;
+
+/// Just the format version of a metadata file.
+#[derive(Deserialize)]
+struct FormatVersion {
+    #[serde(default)]
+    format_version: u32,
+}
+
+/// Content of `.groundhog/location.json`, left in place of the scope's data
+/// when that lives in an external store.
+#[derive(Serialize, Deserialize)]
+struct StoreLocation {
+    store: PathBuf,
+}
+
+/// Create the workspace for `target`. With `store` (as returned by
+/// `prepare_external_store`), metadata, manifests and objects go there and
+/// `.groundhog/` only records where.
+pub fn init_at(target: &Path, password: Option<String>, store: Option<&Path>) -> Result<()> {
     let root = target;
-    let gh_dir = root.join(".groundhog");
+    let gh_dir = groundhog_dir(root);
     if gh_dir.exists() {
         return Err(anyhow!(".groundhog already exists at {}", gh_dir.display()));
     }
-    fs::create_dir_all(gh_dir.join("store"))?;
+    fs::create_dir_all(&gh_dir)?;
+    if let Some(dir) = store {
+        point_to_store(root, Some(dir))?;
+    }
+    let data = data_dir(root)?;
+    fs::create_dir_all(data.join("store"))?;
+    fs::create_dir_all(data.join("objects"))?;
 
     #[cfg(target_os = "windows")]
     {
@@ -30,12 +67,127 @@ pub fn init_at(target: &Path, password: Option<String>) -> Result<()> {
     Ok(())
 }
 
-pub fn store_dir(root: &Path) -> PathBuf {
-    root.join(".groundhog").join("store")
+pub fn groundhog_dir(root: &Path) -> PathBuf {
+    root.join(".groundhog")
+}
+
+fn location_path(root: &Path) -> PathBuf {
+    groundhog_dir(root).join("location.json")
+}
+
+/// The external store holding this scope's data, or `None` if it lives in `.groundhog/`.
+pub fn external_store(root: &Path) -> Result<Option<PathBuf>> {
+    let pointer = location_path(root);
+    if !pointer.exists() {
+        return Ok(None);
+    }
+    let loc: StoreLocation = serde_json::from_str(&fs::read_to_string(&pointer)?)
+        .map_err(|e| anyhow!("invalid store pointer '{}': {}", pointer.display(), e))?;
+    Ok(Some(loc.store))
+}
+
+/// Directory holding the scope's metadata, snapshot manifests and objects:
+/// `.groundhog/` itself, or the external store it points to.
+pub fn data_dir(root: &Path) -> Result<PathBuf> {
+    match external_store(root)? {
+        None => Ok(groundhog_dir(root)),
+        Some(store) if store.is_dir() => Ok(store),
+        Some(store) => Err(anyhow!(
+            "external store '{}' of this scope is missing (is it mounted?)",
+            store.display()
+        )),
+    }
+}
+
+/// Create `dir` for use as an external store of the scope at `root` and return
+/// its absolute path. It must be empty and may not lie inside the scope, where
+/// snapshots would capture it.
+pub fn prepare_external_store(root: &Path, dir: &Path) -> Result<PathBuf> {
+    let created = !dir.exists();
+    fs::create_dir_all(dir)?;
+    let dir = dir.canonicalize()?;
+    let problem = if dir.starts_with(root.canonicalize()?) {
+        Some("lies inside the scope it would hold")
+    } else if fs::read_dir(&dir)?.next().is_some() {
+        Some("is not empty")
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        if created {
+            let _ = fs::remove_dir(&dir);
+        }
+        return Err(anyhow!("store '{}' {}", dir.display(), problem));
+    }
+    Ok(dir)
+}
+
+/// Whether `dir` already holds a scope's data (as an external store does).
+pub fn is_store(dir: &Path) -> bool {
+    dir.join("meta.json").is_file()
+}
+
+/// Recreate a lost `.groundhog/` for a scope whose data survives in the external `store`.
+pub fn attach_store(root: &Path, store: &Path) -> Result<()> {
+    let gh_dir = groundhog_dir(root);
+    if gh_dir.exists() {
+        return Err(anyhow!(".groundhog already exists at {}", gh_dir.display()));
+    }
+    if !is_store(store) {
+        return Err(anyhow!("'{}' holds no groundhog store", store.display()));
+    }
+    fs::create_dir_all(&gh_dir)?;
+    point_to_store(root, Some(&store.canonicalize()?))
+}
+
+/// Point `.groundhog/` at the external `store`, or back at itself for `None`.
+fn point_to_store(root: &Path, store: Option<&Path>) -> Result<()> {
+    match store {
+        Some(dir) => {
+            let loc = StoreLocation { store: dir.to_path_buf() };
+            write_atomic(&location_path(root), serde_json::to_string_pretty(&loc)?.as_bytes())
+        }
+        None => Ok(fs::remove_file(location_path(root))?),
+    }
+}
+
+/// Move the scope's data to the external store `store` (already prepared), or
+/// back into `.groundhog/` for `None`. Everything is copied before the pointer
+/// flips, so an interruption leaves the old location in use and intact.
+pub fn relocate(root: &Path, store: Option<&Path>) -> Result<()> {
+    let old = data_dir(root)?;
+    let new = store.map(Path::to_path_buf).unwrap_or_else(|| groundhog_dir(root));
+    if new == old {
+        return Ok(());
+    }
+    copy_dir_contents(&old, &new, &|name| name == "lock" || name == "location.json")?;
+    point_to_store(root, store)?;
+
+    if old == groundhog_dir(root) {
+        for entry in fs::read_dir(&old)? {
+            let p = entry?.path();
+            if p.file_name().is_some_and(|n| n == "location.json" || n == "lock") {
+                continue;
+            }
+            if p.is_dir() { fs::remove_dir_all(&p)? } else { fs::remove_file(&p)? }
+        }
+    } else {
+        fs::remove_dir_all(&old)?;
+    }
+    Ok(())
+}
+
+pub fn store_dir(root: &Path) -> Result<PathBuf> {
+    Ok(data_dir(root)?.join("store"))
 }
 
-pub fn meta_path(root: &Path) -> PathBuf {
-    root.join(".groundhog").join("meta.json")
+pub fn meta_path(root: &Path) -> Result<PathBuf> {
+    Ok(data_dir(root)?.join("meta.json"))
+}
+
+/// Directory of the snapshot recorded with `directory` (relative to the data dir).
+pub fn snapshot_path(root: &Path, directory: &str) -> Result<PathBuf> {
+    Ok(data_dir(root)?.join(directory))
 }
 
 pub fn snapshot_dir_for(store_dir: &Path, name: &str) -> PathBuf {
@@ -49,31 +201,73 @@ pub fn manifest_path(snapshot_dir: &Path) -> PathBuf {
 
 pub fn save_manifest(snapshot_dir: &Path, tree: &TreeNode) -> Result<()> {
     let p = manifest_path(snapshot_dir);
-    let json = serde_json::to_string_pretty(tree)?;
-    if let Some(parent) = p.parent() { fs::create_dir_all(parent)?; }
-    fs::write(p, json)?;
-    Ok(())
+    let json = serde_json::to_string_pretty(&Manifest { format_version: FORMAT_VERSION, tree })?;
+    write_atomic(&p, json.as_bytes())
 }
 
 pub fn load_manifest(snapshot_dir: &Path) -> Result<TreeNode> {
     let p = manifest_path(snapshot_dir);
     let content = fs::read_to_string(&p)?;
-    let t: TreeNode = serde_json::from_str(&content)?;
-    Ok(t)
+    let value: serde_json::Value = serde_json::from_str(&content)?;
+    let version = migrate::format_version_of(&value);
+    if version != FORMAT_VERSION {
+        migrate::check_supported(version, &p)?;
+        return Err(anyhow!(
+            "manifest '{}' uses outdated format v{}; it was not migrated with its workspace",
+            p.display(),
+            version
+        ));
+    }
+    let m: Manifest<TreeNode> = serde_json::from_value(value)?;
+    Ok(m.tree)
 }
 
+/// Load `meta.json`, first upgrading the workspace in place if it was written
+/// by an older groundhog (see `migrate`).
 pub fn load_config(root: &Path) -> Result<GroundHogConfig> {
-    let meta = meta_path(root);
+    let meta = meta_path(root)?;
     let content = fs::read_to_string(&meta)?;
-    let cfg: GroundHogConfig = serde_json::from_str(&content)?;
+    // A current workspace reads straight into the config; going through a generic
+    // value first is only worth it when there is something to upgrade.
+    let version: FormatVersion = serde_json::from_str(&content)?;
+    if version.format_version == FORMAT_VERSION {
+        return Ok(serde_json::from_str(&content)?);
+    }
+    let value: serde_json::Value = serde_json::from_str(&content)?;
+    let value = migrate::migrate_workspace(root, value)?;
+    let cfg: GroundHogConfig = serde_json::from_value(value)?;
     Ok(cfg)
 }
 
+/// `meta.json` as it is on disk, without upgrading it. For reading before the
+/// workspace lock is held: the upgrade itself must wait for the lock.
+pub fn read_meta_raw(root: &Path) -> Result<serde_json::Value> {
+    let content = fs::read_to_string(meta_path(root)?)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Whether the workspace was written by an older groundhog, so that
+/// `load_config` would upgrade it.
+pub fn needs_upgrade(root: &Path) -> Result<bool> {
+    Ok(migrate::format_version_of(&read_meta_raw(root)?) < FORMAT_VERSION)
+}
+
+/// Take the workspace lock for `command`, waiting as long as `wait` or the
+/// scope's `lock-timeout` setting allows.
+pub fn lock_workspace(root: &Path, command: &str, wait: lock::LockWait) -> Result<lock::WorkspaceLock> {
+    let settings = read_meta_raw(root)
+        .ok()
+        .and_then(|v| v.get("settings").cloned())
+        .and_then(|s| serde_json::from_value::<ScopeSettings>(s).ok())
+        .unwrap_or_default();
+    let timeout = std::time::Duration::from_secs(settings.lock_timeout);
+    lock::acquire(&lock::lock_path(root)?, command, wait, timeout)
+}
+
 pub fn save_config(root: &Path, cfg: &GroundHogConfig) -> Result<()> {
-    let meta = meta_path(root);
+    let meta = meta_path(root)?;
     let content = serde_json::to_string_pretty(cfg)?;
-    fs::write(meta, content)?;
-    Ok(())
+    write_atomic(&meta, content.as_bytes())
 }
 
 fn sanitize(name: &str) -> String {
@@ -0,0 +1,919 @@
+// src/storage/objects.rs
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use fastcdc::v2020::StreamCDC;
+
+use crate::storage;
+use crate::storage::pack::{self, Pack, PackWriter, PACK_MAX_SIZE, PACK_THRESHOLD};
+use crate::utils::compress::is_precompressed;
+use crate::utils::hash::{sha256_bytes, sha256_file};
+
+/// Runs of zeros this long, aligned to it, are left as holes when files are
+/// written out sparsely.
+const HOLE_BLOCK: usize = 4096;
+
+/// Suffix marking an object stored as a zstd frame rather than raw bytes.
+const COMPRESSED_SUFFIX: &str = ".zst";
+
+/// Files at least this large are split into content-defined chunks, so a small
+/// in-place edit only stores the chunks around it instead of the whole file.
+pub const CHUNKING_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// FastCDC minimum / average / maximum chunk sizes.
+const CHUNK_MIN: u32 = 64 * 1024;
+const CHUNK_AVG: u32 = 256 * 1024;
+const CHUNK_MAX: u32 = 1024 * 1024;
+
+/// Chunks cloned out of a file start at multiples of this, the block size
+/// `FICLONERANGE` needs offsets aligned to on common filesystems.
+const CLONE_ALIGN: u64 = 4096;
+
+/// Content-addressed object store living under `.groundhog/objects/`.
+///
+/// Every file captured by a snapshot is stored exactly once, keyed by the
+/// sha256 content hash recorded in the snapshot's `TreeNode` manifest.
+/// Objects up to `PACK_THRESHOLD` bytes are appended to pack files under
+/// `objects/pack/`; larger ones are loose files fanned out into
+/// two-character subdirectories (`ab/cdef...`). Either kind is stored raw or,
+/// when compression is enabled for the scope, zstd-compressed (loose ones as
+/// `<hash>.zst`); readers never need to know which.
+///
+/// Objects appended during a session only become durable once `finish` seals
+/// the open pack.
+pub struct ObjectStore {
+    dir: PathBuf,
+    compression: Option<i32>,
+    packs: Mutex<PackState>,
+    copies: Mutex<CopyStats>,
+}
+
+/// Bytes this store moved into objects or out to files, by how they got there.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CopyStats {
+    /// Shared with their source by a copy-on-write clone, taking no new space.
+    pub cloned: u64,
+    /// Written out (by the kernel or through groundhog).
+    pub copied: u64,
+}
+
+struct PackState {
+    packs: Vec<Pack>,
+    writer: Option<PackWriter>,
+}
+
+impl PackState {
+    fn find(&self, hash: &str) -> Option<(&Pack, pack::PackEntry)> {
+        self.packs
+            .iter()
+            .find_map(|p| p.entries.get(hash).map(|e| (p, *e)))
+    }
+
+    fn contains(&self, hash: &str) -> bool {
+        self.find(hash).is_some()
+            || self.writer.as_ref().is_some_and(|w| w.entries.contains_key(hash))
+    }
+}
+
+impl ObjectStore {
+    /// Open (creating if needed) the object store for the scope rooted at `root`.
+    pub fn open(root: &Path) -> Result<Self> {
+        let dir = objects_dir(root)?;
+        fs::create_dir_all(&dir)?;
+        let packs = pack::load_packs(&dir.join("pack"))?;
+        Ok(Self {
+            dir,
+            compression: None,
+            packs: Mutex::new(PackState { packs, writer: None }),
+            copies: Mutex::default(),
+        })
+    }
+
+    /// Compress newly inserted objects at the given zstd level (`None` stores them raw).
+    pub fn with_compression(mut self, level: Option<i32>) -> Self {
+        self.compression = level;
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, PackState> {
+        self.packs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Bytes cloned and copied by this store so far.
+    pub fn copy_stats(&self) -> CopyStats {
+        *self.copies.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn count(&self, bytes: u64, cloned: bool) {
+        let mut stats = self.copies.lock().unwrap_or_else(|e| e.into_inner());
+        if cloned {
+            stats.cloned += bytes;
+        } else {
+            stats.copied += bytes;
+        }
+    }
+
+    fn pack_dir(&self) -> PathBuf {
+        self.dir.join("pack")
+    }
+
+    fn raw_path(&self, hash: &str) -> PathBuf {
+        let (prefix, rest) = hash.split_at(hash.len().min(2));
+        self.dir.join(prefix).join(rest)
+    }
+
+    fn compressed_path(&self, hash: &str) -> PathBuf {
+        let mut p = self.raw_path(hash).into_os_string();
+        p.push(COMPRESSED_SUFFIX);
+        PathBuf::from(p)
+    }
+
+    /// Location of a loose object on disk, whichever encoding it was stored with.
+    fn loose_path(&self, hash: &str) -> Option<PathBuf> {
+        [self.raw_path(hash), self.compressed_path(hash)]
+            .into_iter()
+            .find(|p| p.is_file())
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.state().contains(hash) || self.loose_path(hash).is_some()
+    }
+
+    /// Bytes the object occupies on disk (after compression).
+    pub fn stored_size(&self, hash: &str) -> Option<u64> {
+        if let Some((_, entry)) = self.state().find(hash) {
+            return Some(entry.length);
+        }
+        let p = self.loose_path(hash)?;
+        fs::metadata(p).ok().map(|m| m.len())
+    }
+
+    /// Copy `src` into the store under `hash`. The content is re-hashed while
+    /// copying so a file modified after tree construction is never stored under
+    /// a stale key. Returns `true` if a new object was written.
+    pub fn insert_file(&self, src: &Path, hash: &str) -> Result<bool> {
+        if self.contains(hash) {
+            return Ok(false);
+        }
+        if fs::metadata(src)?.len() <= PACK_THRESHOLD {
+            let data = fs::read(src)?;
+            if sha256_bytes(&data) != hash {
+                return Err(anyhow!(
+                    "'{}' changed while the snapshot was being taken",
+                    src.display()
+                ));
+            }
+            let compressible = self.compression.is_some() && !is_precompressed(src)?;
+            return self.insert_bytes(hash, &data, compressible);
+        }
+
+        let dest = self.raw_path(hash);
+        let parent = dest.parent().ok_or_else(|| anyhow!("invalid object path"))?;
+        fs::create_dir_all(parent)?;
+        let tmp = parent.join(format!(".{}.tmp", hash));
+
+        if let Some(level) = self.compression
+            && !is_precompressed(src)?
+        {
+            let (actual, logical) = compress_hashing(src, &tmp, level)?;
+            check_hash(src, &tmp, &actual, hash)?;
+            // Keep the compressed form only when it actually saves space.
+            if fs::metadata(&tmp)?.len() < logical {
+                fs::rename(&tmp, self.compressed_path(hash))?;
+                self.count(logical, false);
+                return Ok(true);
+            }
+            fs::remove_file(&tmp)?;
+        }
+
+        if self.clone_into(src, hash)? {
+            return Ok(true);
+        }
+        let actual = copy_hashing(src, &tmp)?;
+        check_hash(src, &tmp, &actual, hash)?;
+        fs::rename(&tmp, &dest)?;
+        self.count(fs::metadata(&dest)?.len(), false);
+        Ok(true)
+    }
+
+    /// Clone `src` into the loose object `hash`, checking its content on the way.
+    fn clone_into(&self, src: &Path, hash: &str) -> Result<bool> {
+        let dest = self.raw_path(hash);
+        let parent = dest.parent().ok_or_else(|| anyhow!("invalid object path"))?;
+        fs::create_dir_all(parent)?;
+        let tmp = parent.join(format!(".{}.tmp", hash));
+        let input = File::open(src)?;
+        let output = File::create(&tmp)?;
+        if !clone_file(&input, &output) {
+            drop(output);
+            fs::remove_file(&tmp)?;
+            return Ok(false);
+        }
+        output.sync_all()?;
+        // Reading the clone back costs no more than the copy would have.
+        check_hash(src, &tmp, &sha256_file(&tmp)?, hash)?;
+        fs::rename(&tmp, &dest)?;
+        self.count(input.metadata()?.len(), true);
+        Ok(true)
+    }
+
+    /// Split `src` into content-defined chunks and store each one as its own
+    /// object. Returns the chunk hashes in file order. As with `insert_file`,
+    /// the whole file is re-hashed on the way and must still match `hash`.
+    pub fn insert_chunked(&self, src: &Path, hash: &str) -> Result<Vec<String>> {
+        if self.compression.is_none()
+            && let Some(chunks) = self.insert_chunked_clone(src, hash)?
+        {
+            return Ok(chunks);
+        }
+        let compressible = self.compression.is_some() && !is_precompressed(src)?;
+        let mut hasher = Sha256::new();
+        let mut chunks = Vec::new();
+        for chunk in StreamCDC::new(File::open(src)?, CHUNK_MIN, CHUNK_AVG, CHUNK_MAX) {
+            let chunk = chunk.map_err(|e| anyhow!("failed to chunk '{}': {}", src.display(), e))?;
+            hasher.update(&chunk.data);
+            let chunk_hash = sha256_bytes(&chunk.data);
+            self.insert_bytes(&chunk_hash, &chunk.data, compressible)?;
+            chunks.push(chunk_hash);
+        }
+        if hex::encode(hasher.finalize()) != hash {
+            return Err(anyhow!(
+                "'{}' changed while the snapshot was being taken",
+                src.display()
+            ));
+        }
+        Ok(chunks)
+    }
+
+    /// `insert_chunked` where the store's filesystem can share extents (btrfs, XFS
+    /// and the like): `src` is cloned to a private copy, which is cut at block-aligned
+    /// content-defined boundaries, and each chunk is cloned out of it as a loose
+    /// object. Chunks then take no new space and clone back on rollback. `None`,
+    /// storing nothing, when the store cannot clone `src`.
+    fn insert_chunked_clone(&self, src: &Path, hash: &str) -> Result<Option<Vec<String>>> {
+        let parent = self.raw_path(hash).parent().ok_or_else(|| anyhow!("invalid object path"))?.to_path_buf();
+        fs::create_dir_all(&parent)?;
+        let copy = parent.join(format!(".{}.tmp", hash));
+        let cloned = clone_file(&File::open(src)?, &File::create(&copy)?);
+        let chunks = if cloned { self.clone_chunks(&copy, hash).map(Some) } else { Ok(None) };
+        fs::remove_file(&copy)?;
+        chunks.map_err(|e| e.context(format!("failed to store '{}'", src.display())))
+    }
+
+    /// Chunk the private clone `copy` of a file, cloning each chunk out of it.
+    fn clone_chunks(&self, copy: &Path, hash: &str) -> Result<Vec<String>> {
+        let file = File::open(copy)?;
+        let mut hasher = Sha256::new();
+        let mut chunks = Vec::new();
+        // Data read but not yet stored, and where in the file it starts.
+        let mut pending = Vec::new();
+        let mut offset = 0u64;
+        for chunk in StreamCDC::new(File::open(copy)?, CHUNK_MIN, CHUNK_AVG, CHUNK_MAX) {
+            let chunk = chunk.map_err(|e| anyhow!("failed to chunk: {}", e))?;
+            hasher.update(&chunk.data);
+            pending.extend_from_slice(&chunk.data);
+            let end = chunk.offset + chunk.length as u64;
+            let cut = end - end % CLONE_ALIGN;
+            if cut > offset {
+                let n = (cut - offset) as usize;
+                chunks.push(self.insert_range(&file, offset, &pending[..n])?);
+                pending.drain(..n);
+                offset = cut;
+            }
+        }
+        if !pending.is_empty() {
+            chunks.push(self.insert_range(&file, offset, &pending)?);
+        }
+        if hex::encode(hasher.finalize()) != hash {
+            return Err(anyhow!("the file changed while the snapshot was being taken"));
+        }
+        Ok(chunks)
+    }
+
+    /// Store `data`, found at `offset` in `file`, as a chunk object: a loose clone
+    /// of that range where the filesystem allows, else as `insert_bytes` would.
+    fn insert_range(&self, file: &File, offset: u64, data: &[u8]) -> Result<String> {
+        let hash = sha256_bytes(data);
+        if self.contains(&hash) {
+            return Ok(hash);
+        }
+        let dest = self.raw_path(&hash);
+        let parent = dest.parent().ok_or_else(|| anyhow!("invalid object path"))?;
+        fs::create_dir_all(parent)?;
+        let tmp = parent.join(format!(".{}.tmp", hash));
+        let out = File::create(&tmp)?;
+        if clone_range(file, offset, data.len() as u64, &out, 0) {
+            out.sync_all()?;
+            fs::rename(&tmp, &dest)?;
+            self.count(data.len() as u64, true);
+        } else {
+            drop(out);
+            fs::remove_file(&tmp)?;
+            self.insert_bytes(&hash, data, false)?;
+        }
+        Ok(hash)
+    }
+
+    /// Store an object streamed from elsewhere (e.g. a remote), refusing it unless
+    /// its content matches `hash`. Objects too large for a pack never sit in memory
+    /// whole. Returns the object's size.
+    pub fn insert_stream(&self, hash: &str, data: &mut dyn Read) -> Result<u64> {
+        let mut head = Vec::new();
+        (&mut *data).take(PACK_THRESHOLD + 1).read_to_end(&mut head)?;
+        if head.len() as u64 <= PACK_THRESHOLD {
+            if sha256_bytes(&head) != hash {
+                return Err(anyhow!("object {} does not match its hash", hash));
+            }
+            self.insert_bytes(hash, &head, true)?;
+            return Ok(head.len() as u64);
+        }
+
+        let dest = self.raw_path(hash);
+        let parent = dest.parent().ok_or_else(|| anyhow!("invalid object path"))?;
+        fs::create_dir_all(parent)?;
+        let tmp = parent.join(format!(".{}.tmp", hash));
+        let mut input = HashingReader { inner: Cursor::new(head).chain(data), hasher: Sha256::new() };
+        let mut output = File::create(&tmp)?;
+        let (len, _) = copy_sparse(&mut input, &mut output)?;
+        output.sync_all()?;
+        if hex::encode(input.hasher.finalize()) != hash {
+            fs::remove_file(&tmp)?;
+            return Err(anyhow!("object {} does not match its hash", hash));
+        }
+
+        if let Some(level) = self.compression {
+            let packed = parent.join(format!(".{}.zst.tmp", hash));
+            compress_hashing(&tmp, &packed, level)?;
+            // Keep the compressed form only when it actually saves space.
+            if fs::metadata(&packed)?.len() < len {
+                fs::rename(&packed, self.compressed_path(hash))?;
+                fs::remove_file(&tmp)?;
+                self.count(len, false);
+                return Ok(len);
+            }
+            fs::remove_file(&packed)?;
+        }
+        fs::rename(&tmp, &dest)?;
+        self.count(len, false);
+        Ok(len)
+    }
+
+    /// Store an in-memory object. Returns `true` if a new object was written.
+    fn insert_bytes(&self, hash: &str, data: &[u8], compressible: bool) -> Result<bool> {
+        if self.contains(hash) {
+            return Ok(false);
+        }
+
+        let mut encoded = None;
+        if let Some(level) = self.compression.filter(|_| compressible) {
+            let packed = zstd::bulk::compress(data, level)?;
+            if packed.len() < data.len() {
+                encoded = Some(packed);
+            }
+        }
+        let compressed = encoded.is_some();
+        let bytes = encoded.as_deref().unwrap_or(data);
+
+        if data.len() as u64 <= PACK_THRESHOLD {
+            let mut state = self.state();
+            if state.writer.as_ref().is_some_and(|w| w.size() >= PACK_MAX_SIZE)
+                && let Some(sealed) = state.writer.take().map(PackWriter::finish).transpose()?.flatten()
+            {
+                state.packs.push(sealed);
+            }
+            if state.writer.is_none() {
+                state.writer = Some(PackWriter::create(&self.pack_dir())?);
+            }
+            if let Some(w) = state.writer.as_mut() {
+                w.append(hash, bytes, compressed)?;
+            }
+            drop(state);
+            self.count(data.len() as u64, false);
+            return Ok(true);
+        }
+
+        let dest = if compressed { self.compressed_path(hash) } else { self.raw_path(hash) };
+        let parent = dest.parent().ok_or_else(|| anyhow!("invalid object path"))?;
+        fs::create_dir_all(parent)?;
+        let tmp = parent.join(format!(".{}.tmp", hash));
+        let mut out = File::create(&tmp)?;
+        out.write_all(bytes)?;
+        out.sync_all()?;
+        fs::rename(&tmp, &dest)?;
+        self.count(data.len() as u64, false);
+        Ok(true)
+    }
+
+    /// Seal the pack being written, making its objects durable and visible to
+    /// later sessions. Must be called before a manifest referencing them is saved.
+    pub fn finish(&self) -> Result<()> {
+        let mut state = self.state();
+        if let Some(sealed) = state.writer.take().map(PackWriter::finish).transpose()?.flatten() {
+            state.packs.push(sealed);
+        }
+        Ok(())
+    }
+
+    /// Size of the object's original (decompressed) content.
+    pub fn object_len(&self, hash: &str) -> Result<u64> {
+        let raw = self.raw_path(hash);
+        if self.state().find(hash).is_none() && raw.is_file() {
+            return Ok(fs::metadata(raw)?.len());
+        }
+        // zstd frames need not record their content size, so count it.
+        Ok(io::copy(&mut self.open_object(hash)?, &mut io::sink())?)
+    }
+
+    /// Open the object `hash` for reading its original (decompressed) content.
+    pub fn open_object(&self, hash: &str) -> Result<Box<dyn Read>> {
+        if let Some((pack, entry)) = self.state().find(hash) {
+            let data = pack.read_entry(&entry)?;
+            let data = if entry.compressed { zstd::bulk::decompress(&data, PACK_THRESHOLD as usize)? } else { data };
+            return Ok(Box::new(Cursor::new(data)));
+        }
+        let raw = self.raw_path(hash);
+        if raw.is_file() {
+            return Ok(Box::new(File::open(raw)?));
+        }
+        let compressed = self.compressed_path(hash);
+        if compressed.is_file() {
+            return Ok(Box::new(zstd::stream::Decoder::new(File::open(compressed)?)?));
+        }
+        Err(anyhow!("object {} is missing from the store", hash))
+    }
+
+    /// Write the object `hash` to `dest`, replacing whatever is there. With
+    /// `sparse`, blocks of zeros are left as holes.
+    pub fn restore_file(&self, hash: &str, dest: &Path, sparse: bool) -> Result<()> {
+        // A loose raw object is a file already: the filesystem may share its extents
+        // with `dest`, or at least copy it without going through groundhog.
+        let raw = self.raw_path(hash);
+        if self.state().find(hash).is_none() && raw.is_file() {
+            let mut input = File::open(raw)?;
+            let mut out = create_dest(dest)?;
+            if clone_file(&input, &out) {
+                self.count(input.metadata()?.len(), true);
+            } else if sparse {
+                let (_, written) = copy_sparse(&mut input, &mut out)?;
+                self.count(written, false);
+            } else {
+                // Uses copy_file_range where the kernel has it.
+                self.count(io::copy(&mut input, &mut out)?, false);
+            }
+            return Ok(());
+        }
+
+        let mut reader = self.open_object(hash)?;
+        let mut out = create_dest(dest)?;
+        let written = if sparse { copy_sparse(&mut reader, &mut out)?.1 } else { io::copy(&mut reader, &mut out)? };
+        self.count(written, false);
+        Ok(())
+    }
+
+    /// Reassemble a chunked file at `dest` from its chunk objects, in order.
+    /// Chunks kept as loose raw objects are cloned into place where the filesystem
+    /// allows; the rest are written out, with `sparse` leaving blocks of zeros as holes.
+    pub fn restore_chunks(&self, chunks: &[String], dest: &Path, sparse: bool) -> Result<()> {
+        let mut out = create_dest(dest)?;
+        let mut offset = 0u64;
+        for chunk in chunks {
+            let raw = self.raw_path(chunk);
+            if self.state().find(chunk).is_none() && raw.is_file() {
+                let input = File::open(raw)?;
+                let len = input.metadata()?.len();
+                if clone_range(&input, 0, len, &out, offset) {
+                    self.count(len, true);
+                    offset += len;
+                    continue;
+                }
+            }
+            out.seek(SeekFrom::Start(offset))?;
+            let mut reader = self.open_object(chunk)?;
+            let (len, written) = if sparse {
+                write_sparse(&mut reader, &mut out)?
+            } else {
+                let len = io::copy(&mut reader, &mut out)?;
+                (len, len)
+            };
+            self.count(written, false);
+            offset += len;
+        }
+        // Trailing holes are only there once the length covers them.
+        out.set_len(offset)?;
+        Ok(())
+    }
+
+    /// Every object in the store, loose or packed, as (hash, size on disk), sorted by hash.
+    pub fn list(&self) -> Result<Vec<(String, u64)>> {
+        let mut out = BTreeMap::new();
+        for pack in &self.state().packs {
+            for (hash, entry) in &pack.entries {
+                out.insert(hash.clone(), entry.length);
+            }
+        }
+        for fan in fs::read_dir(&self.dir)? {
+            let fan = fan?;
+            let prefix = fan.file_name().to_string_lossy().to_string();
+            // Only the two-character fan-out directories hold loose objects.
+            if !fan.file_type()?.is_dir() || prefix.len() != 2 {
+                continue;
+            }
+            for entry in fs::read_dir(fan.path())? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                // Skip in-flight temp files from `insert_file`.
+                if name.starts_with('.') {
+                    continue;
+                }
+                let rest = name.strip_suffix(COMPRESSED_SUFFIX).unwrap_or(&name);
+                out.insert(format!("{}{}", prefix, rest), entry.metadata()?.len());
+            }
+        }
+        Ok(out.into_iter().collect())
+    }
+
+    /// Delete the loose copy of `hash`, pruning its fan-out directory once empty.
+    /// Packed objects are immutable; they are dropped when `repack` rewrites their pack.
+    pub fn remove(&self, hash: &str) -> Result<()> {
+        let Some(p) = self.loose_path(hash) else {
+            return Ok(());
+        };
+        fs::remove_file(&p)?;
+        if let Some(parent) = p.parent() {
+            // Fails harmlessly while other objects share the directory.
+            let _ = fs::remove_dir(parent);
+        }
+        Ok(())
+    }
+
+    /// Delete leftovers of writes that never completed: loose `.<hash>.tmp` files
+    /// and unsealed `.tmp-*` packs. Only safe while no other writer is active.
+    pub fn remove_temp_files(&self) -> Result<()> {
+        for fan in fs::read_dir(&self.dir)? {
+            let fan = fan?;
+            if !fan.file_type()?.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(fan.path())? {
+                let entry = entry?;
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge packs, dropping every entry `keep` rejects. Packs holding dead
+    /// entries or filled to less than half of `PACK_MAX_SIZE` are rewritten
+    /// into as few new packs as possible; full, clean packs are left alone.
+    /// Returns how many packs were merged away.
+    pub fn repack<F>(&self, keep: F) -> Result<usize>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let mut state = self.state();
+        let (candidates, kept): (Vec<Pack>, Vec<Pack>) =
+            std::mem::take(&mut state.packs).into_iter().partition(|p| {
+                p.entries.keys().any(|h| !keep(h)) || p.size() < PACK_MAX_SIZE / 2
+            });
+        state.packs = kept;
+
+        let has_dead = candidates.iter().any(|p| p.entries.keys().any(|h| !keep(h)));
+        if candidates.len() < 2 && !has_dead {
+            state.packs.extend(candidates);
+            return Ok(0);
+        }
+
+        let merged_from = state.packs.len();
+        let mut writer = PackWriter::create(&self.pack_dir())?;
+        for pack in &candidates {
+            for (hash, entry) in &pack.entries {
+                if !keep(hash) || writer.entries.contains_key(hash) {
+                    continue;
+                }
+                if writer.size() >= PACK_MAX_SIZE {
+                    let full = std::mem::replace(&mut writer, PackWriter::create(&self.pack_dir())?);
+                    state.packs.extend(full.finish()?);
+                }
+                writer.append(hash, &pack.read_entry(entry)?, entry.compressed)?;
+            }
+        }
+        state.packs.extend(writer.finish()?);
+
+        // The merged packs are durable; only now drop the originals. Packs are
+        // named after their content, so a rewrite identical to an original
+        // landed on the very same file and must be kept.
+        let written: Vec<PathBuf> = state.packs[merged_from..].iter().map(|p| p.path.clone()).collect();
+        for pack in &candidates {
+            if !written.contains(&pack.path) {
+                pack.delete()?;
+            }
+        }
+        Ok(candidates.len())
+    }
+}
+
+pub fn objects_dir(root: &Path) -> Result<PathBuf> {
+    Ok(storage::data_dir(root)?.join("objects"))
+}
+
+fn check_hash(src: &Path, tmp: &Path, actual: &str, expected: &str) -> Result<()> {
+    if actual != expected {
+        let _ = fs::remove_file(tmp);
+        return Err(anyhow!(
+            "'{}' changed while the snapshot was being taken",
+            src.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Stream `src` into `dest`, returning the hex sha256 of the bytes copied.
+/// Blocks of zeros become holes, so a sparse file stays sparse in the store.
+fn copy_hashing(src: &Path, dest: &Path) -> Result<String> {
+    let mut input = HashingReader { inner: File::open(src)?, hasher: Sha256::new() };
+    let mut output = File::create(dest)?;
+    copy_sparse(&mut input, &mut output)?;
+    output.sync_all()?;
+    Ok(hex::encode(input.hasher.finalize()))
+}
+
+/// Make the empty file `out` a copy-on-write clone of `src` (FICLONE, on btrfs,
+/// XFS and the like). `false` when the filesystem cannot, or the two are on
+/// different ones; `out` is then left as it was.
+fn clone_file(src: &File, out: &File) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use std::os::fd::AsRawFd;
+        unsafe { libc::ioctl(out.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) == 0 }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = (src, out);
+        false
+    }
+}
+
+/// Make `len` bytes of `src` from `offset` on share their extents with `out` at
+/// `dest_offset` (FICLONERANGE). Offsets must be block-aligned, as must `len`
+/// unless the range ends `src`. `false` when that is not possible.
+fn clone_range(src: &File, offset: u64, len: u64, out: &File, dest_offset: u64) -> bool {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use std::os::fd::AsRawFd;
+        let range = libc::file_clone_range {
+            src_fd: src.as_raw_fd() as i64,
+            src_offset: offset,
+            src_length: len,
+            dest_offset,
+        };
+        unsafe { libc::ioctl(out.as_raw_fd(), libc::FICLONERANGE, &range) == 0 }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = (src, offset, len, out, dest_offset);
+        false
+    }
+}
+
+/// Hashes everything read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Create (or truncate) `dest` for a restored file, replacing a directory in the way.
+fn create_dest(dest: &Path) -> Result<File> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if dest.is_dir() {
+        fs::remove_dir_all(dest)?;
+    }
+    Ok(File::create(dest)?)
+}
+
+/// Copy `reader` into the empty file `out`, seeking over blocks of `HOLE_BLOCK`
+/// zeros instead of writing them, so the filesystem leaves holes there. Returns
+/// the length copied and how many bytes of it were actually written.
+fn copy_sparse(reader: &mut dyn Read, out: &mut File) -> io::Result<(u64, u64)> {
+    let (total, written) = write_sparse(reader, out)?;
+    // Trailing holes are only there once the length covers them.
+    out.set_len(total)?;
+    Ok((total, written))
+}
+
+/// Write `reader` to `out` from its current position on, seeking over blocks of
+/// `HOLE_BLOCK` zeros; a hole at the end is left for the caller to cover.
+fn write_sparse(reader: &mut dyn Read, out: &mut File) -> io::Result<(u64, u64)> {
+    let mut buf = vec![0u8; 64 * HOLE_BLOCK];
+    let mut total = 0u64;
+    let mut written = 0u64;
+    loop {
+        // Filled completely where possible, so blocks stay aligned to the file's.
+        let mut n = 0;
+        while n < buf.len() {
+            match reader.read(&mut buf[n..])? {
+                0 => break,
+                read => n += read,
+            }
+        }
+        if n == 0 {
+            break;
+        }
+        for block in buf[..n].chunks(HOLE_BLOCK) {
+            if block.iter().all(|&b| b == 0) {
+                out.seek(SeekFrom::Current(block.len() as i64))?;
+            } else {
+                out.write_all(block)?;
+                written += block.len() as u64;
+            }
+        }
+        total += n as u64;
+    }
+    Ok((total, written))
+}
+
+/// Stream `src` through zstd into `dest`, returning the hex sha256 of the
+/// uncompressed bytes and how many of them there were.
+fn compress_hashing(src: &Path, dest: &Path, level: i32) -> Result<(String, u64)> {
+    let mut input = File::open(src)?;
+    let mut encoder = zstd::stream::Encoder::new(File::create(dest)?, level)?;
+    let mut hasher = Sha256::new();
+    let mut total = 0u64;
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
+        encoder.write_all(&buf[..n])?;
+        total += n as u64;
+    }
+    encoder.finish()?.sync_all()?;
+    Ok((hex::encode(hasher.finalize()), total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic bytes that neither compress nor repeat.
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    fn read_object(store: &ObjectStore, hash: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        store.open_object(hash).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    /// Store each batch of `objects` in a pack of its own, returning their hashes.
+    fn pack_batches(store: &ObjectStore, batches: &[&[&[u8]]]) -> Vec<String> {
+        let mut hashes = Vec::new();
+        for batch in batches {
+            for data in *batch {
+                let hash = sha256_bytes(data);
+                assert!(store.insert_bytes(&hash, data, false).unwrap());
+                hashes.push(hash);
+            }
+            store.finish().unwrap();
+        }
+        hashes
+    }
+
+    fn pack_count(store: &ObjectStore) -> usize {
+        store.state().packs.len()
+    }
+
+    #[test]
+    fn packed_objects_survive_repack() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        let data: [&[u8]; 3] = [b"first", b"second", b"third"];
+        let hashes = pack_batches(&store, &[&data[..2], &data[2..]]);
+        assert_eq!(pack_count(&store), 2);
+
+        assert_eq!(store.repack(|_| true).unwrap(), 2);
+        assert_eq!(pack_count(&store), 1);
+        // Read back through the index a fresh session loads, not just memory.
+        let store = ObjectStore::open(dir.path()).unwrap();
+        assert_eq!(pack_count(&store), 1);
+        for (hash, data) in hashes.iter().zip(data) {
+            assert_eq!(read_object(&store, hash), data);
+        }
+        assert_eq!(fs::read_dir(store.pack_dir()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn repack_drops_dead_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        let live = noise(100 * 1024, 5);
+        let dead = noise(200 * 1024, 6);
+        let hashes = pack_batches(&store, &[&[&live, &dead]]);
+        let before: u64 = store.list().unwrap().iter().map(|(_, size)| size).sum();
+
+        assert_eq!(store.repack(|hash| hash == hashes[0]).unwrap(), 1);
+        let store = ObjectStore::open(dir.path()).unwrap();
+        assert!(store.contains(&hashes[0]));
+        assert!(!store.contains(&hashes[1]));
+        assert!(store.open_object(&hashes[1]).is_err());
+        assert_eq!(read_object(&store, &hashes[0]), live);
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].1 < before);
+    }
+
+    #[test]
+    fn repack_keeps_live_packs_it_merges() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        let data: [&[u8]; 2] = [b"live", b"dead"];
+        let hashes = pack_batches(&store, &[&data[..1], &data[1..]]);
+
+        // Rewriting the first pack on its own yields that very pack again.
+        assert_eq!(store.repack(|hash| hash == hashes[0]).unwrap(), 2);
+        let store = ObjectStore::open(dir.path()).unwrap();
+        assert_eq!(pack_count(&store), 1);
+        assert_eq!(read_object(&store, &hashes[0]), b"live");
+        assert!(!store.contains(&hashes[1]));
+    }
+
+    #[test]
+    fn streams_objects_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+
+        let small = noise(1000, 1);
+        let large = noise(3 * 1024 * 1024, 2);
+        for data in [&small, &large] {
+            let hash = sha256_bytes(data);
+            assert_eq!(store.insert_stream(&hash, &mut data.as_slice()).unwrap(), data.len() as u64);
+            store.finish().unwrap();
+            assert_eq!(&read_object(&store, &hash), data);
+            assert_eq!(store.object_len(&hash).unwrap(), data.len() as u64);
+        }
+
+        let wrong = sha256_bytes(b"something else");
+        assert!(store.insert_stream(&wrong, &mut large.as_slice()).is_err());
+        assert!(!store.contains(&wrong));
+        assert!(store.list().unwrap().iter().all(|(hash, _)| hash != &wrong));
+    }
+
+    #[test]
+    fn chunked_files_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        let mut data = noise(5 * 1024 * 1024, 3);
+        data.extend(vec![0; 2 * 1024 * 1024]);
+        let src = dir.path().join("big");
+        fs::write(&src, &data).unwrap();
+
+        let chunks = store.insert_chunked(&src, &sha256_bytes(&data)).unwrap();
+        store.finish().unwrap();
+        assert!(chunks.len() > 1);
+        for sparse in [false, true] {
+            let dest = dir.path().join(format!("restored-{}", sparse));
+            store.restore_chunks(&chunks, &dest, sparse).unwrap();
+            assert!(fs::read(&dest).unwrap() == data);
+        }
+    }
+
+    #[test]
+    fn sparse_restores_count_written_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ObjectStore::open(dir.path()).unwrap();
+        let mut data = vec![0; 2 * 1024 * 1024];
+        data.extend(noise(HOLE_BLOCK, 4));
+        let hash = sha256_bytes(&data);
+        store.insert_stream(&hash, &mut data.as_slice()).unwrap();
+        let before = store.copy_stats().copied;
+
+        let dest = dir.path().join("restored");
+        store.restore_file(&hash, &dest, true).unwrap();
+        assert!(fs::read(&dest).unwrap() == data);
+        assert_eq!(store.copy_stats().copied - before, HOLE_BLOCK as u64);
+    }
+}
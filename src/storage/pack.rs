@@ -0,0 +1,191 @@
+// src/storage/pack.rs
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::utils::io::write_atomic;
+
+/// Objects whose content is at most this many bytes go into pack files
+/// instead of getting a loose file (and an inode) of their own.
+pub const PACK_THRESHOLD: u64 = 1024 * 1024;
+
+/// A pack stops accepting objects once it grows past this size.
+pub const PACK_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+const PACK_MAGIC: &[u8; 8] = b"GHPACK1\n";
+const INDEX_MAGIC: &[u8; 8] = b"GHIDX1\n\0";
+/// sha256 (32) + offset (8) + length (8) + flags (1)
+const INDEX_RECORD_LEN: usize = 49;
+const FLAG_COMPRESSED: u8 = 1;
+
+/// Where one object lives inside a pack file.
+#[derive(Clone, Copy, Debug)]
+pub struct PackEntry {
+    pub offset: u64,
+    pub length: u64,
+    /// Entry bytes are a zstd frame rather than the raw object.
+    pub compressed: bool,
+}
+
+/// A finished, immutable pack: `pack-<id>.pack` plus its `pack-<id>.idx`.
+pub struct Pack {
+    pub path: PathBuf,
+    pub entries: BTreeMap<String, PackEntry>,
+}
+
+impl Pack {
+    pub fn index_path(&self) -> PathBuf {
+        self.path.with_extension("idx")
+    }
+
+    /// Size of the pack file on disk.
+    pub fn size(&self) -> u64 {
+        fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0)
+    }
+
+    /// Read the stored bytes of an entry (still compressed if `entry.compressed`).
+    pub fn read_entry(&self, entry: &PackEntry) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut buf = vec![0u8; entry.length as usize];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Delete both the pack and its index. The index goes first so a crash
+    /// in between leaves an unindexed pack, which is ignored, rather than an
+    /// index pointing at nothing.
+    pub fn delete(&self) -> Result<()> {
+        fs::remove_file(self.index_path())?;
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
+
+/// Load every finished pack in `dir`. Packs without an index (interrupted
+/// writes) are skipped.
+pub fn load_packs(dir: &Path) -> Result<Vec<Pack>> {
+    let mut packs = Vec::new();
+    if !dir.is_dir() {
+        return Ok(packs);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("idx") {
+            continue;
+        }
+        let pack_path = path.with_extension("pack");
+        if !pack_path.is_file() {
+            continue;
+        }
+        packs.push(Pack { entries: read_index(&path)?, path: pack_path });
+    }
+    packs.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(packs)
+}
+
+fn read_index(path: &Path) -> Result<BTreeMap<String, PackEntry>> {
+    let data = fs::read(path)?;
+    if data.len() < 16 || &data[..8] != INDEX_MAGIC {
+        return Err(anyhow!("'{}' is not a pack index", path.display()));
+    }
+    let count = u64::from_le_bytes(data[8..16].try_into()?) as usize;
+    let records = &data[16..];
+    if records.len() != count * INDEX_RECORD_LEN {
+        return Err(anyhow!("pack index '{}' is truncated", path.display()));
+    }
+
+    let mut entries = BTreeMap::new();
+    for rec in records.chunks_exact(INDEX_RECORD_LEN) {
+        let hash = hex::encode(&rec[..32]);
+        let offset = u64::from_le_bytes(rec[32..40].try_into()?);
+        let length = u64::from_le_bytes(rec[40..48].try_into()?);
+        let compressed = rec[48] & FLAG_COMPRESSED != 0;
+        entries.insert(hash, PackEntry { offset, length, compressed });
+    }
+    Ok(entries)
+}
+
+fn write_index(path: &Path, entries: &BTreeMap<String, PackEntry>) -> Result<()> {
+    let mut buf = Vec::with_capacity(16 + entries.len() * INDEX_RECORD_LEN);
+    buf.extend_from_slice(INDEX_MAGIC);
+    buf.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    for (hash, e) in entries {
+        buf.extend_from_slice(&hex::decode(hash)?);
+        buf.extend_from_slice(&e.offset.to_le_bytes());
+        buf.extend_from_slice(&e.length.to_le_bytes());
+        buf.push(if e.compressed { FLAG_COMPRESSED } else { 0 });
+    }
+    write_atomic(path, &buf)
+}
+
+/// Appends objects to a new pack. Nothing is visible to other readers until
+/// `finish` names the pack after its content and writes the index.
+pub struct PackWriter {
+    dir: PathBuf,
+    tmp: PathBuf,
+    out: BufWriter<File>,
+    hasher: Sha256,
+    offset: u64,
+    pub entries: BTreeMap<String, PackEntry>,
+}
+
+impl PackWriter {
+    pub fn create(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let tmp = dir.join(format!(
+            ".tmp-{}-{}.pack",
+            std::process::id(),
+            chrono::Local::now().format("%Y%m%d%H%M%S%f")
+        ));
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(PACK_MAGIC)?;
+        let mut hasher = Sha256::new();
+        hasher.update(PACK_MAGIC);
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            tmp,
+            out,
+            hasher,
+            offset: PACK_MAGIC.len() as u64,
+            entries: BTreeMap::new(),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn append(&mut self, hash: &str, data: &[u8], compressed: bool) -> Result<()> {
+        self.out.write_all(data)?;
+        self.hasher.update(data);
+        let entry = PackEntry { offset: self.offset, length: data.len() as u64, compressed };
+        self.entries.insert(hash.to_string(), entry);
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    /// Seal the pack. Returns `None` (and removes the temp file) if nothing was appended.
+    pub fn finish(self) -> Result<Option<Pack>> {
+        let file = self.out.into_inner().map_err(|e| e.into_error())?;
+        if self.entries.is_empty() {
+            drop(file);
+            fs::remove_file(&self.tmp)?;
+            return Ok(None);
+        }
+        file.sync_all()?;
+        drop(file);
+
+        let id = hex::encode(self.hasher.finalize());
+        let path = self.dir.join(format!("pack-{}.pack", &id[..16]));
+        fs::rename(&self.tmp, &path)?;
+        let pack = Pack { path, entries: self.entries };
+
+        write_index(&pack.index_path(), &pack.entries)?;
+        Ok(Some(pack))
+    }
+}
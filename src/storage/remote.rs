@@ -0,0 +1,147 @@
+// src/storage/remote.rs
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::config::groundhog::{Snapshot, TreeNode, FORMAT_VERSION};
+use crate::storage::{self, backend::Backend, objects::ObjectStore};
+use crate::utils::hash::flatten_tree;
+
+/// A snapshot as kept in a remote: its metadata entry and its tree, stored as
+/// `snapshots/<id>.json`. File contents live beside it as `objects/<hash>`.
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    pub format_version: u32,
+    pub snapshot: Snapshot,
+    pub tree: TreeNode,
+}
+
+/// Objects copied by an upload or download; ones already present are not counted.
+#[derive(Debug, Default)]
+pub struct TransferStats {
+    pub objects: usize,
+    pub bytes: u64,
+}
+
+/// Identifies a snapshot across stores, whatever its name or folder there:
+/// folder names only carry the time to the second, so two scopes can produce
+/// the same one, while the creation time is kept to the nanosecond in every copy.
+pub fn identity(snap: &Snapshot) -> DateTime<Local> {
+    snap.created_at
+}
+
+/// The name of a snapshot's folder in its store, which combines its timestamp
+/// and name; records in a remote are named after it where possible.
+pub fn snapshot_id(snap: &Snapshot) -> String {
+    Path::new(&snap.directory)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| snap.directory.clone())
+}
+
+fn record_key(id: &str) -> String {
+    format!("snapshots/{}.json", id)
+}
+
+fn object_key(hash: &str) -> String {
+    format!("objects/{}", hash)
+}
+
+fn object_hashes(tree: &TreeNode) -> BTreeSet<String> {
+    flatten_tree(tree)
+        .values()
+        .filter(|n| !n.is_dir && !n.hash.is_empty())
+        .flat_map(|n| n.object_hashes())
+        .map(String::from)
+        .collect()
+}
+
+/// Copy a local snapshot and whatever of its data the remote lacks to `backend`.
+pub fn upload_snapshot(root: &Path, snap: &Snapshot, backend: &dyn Backend) -> Result<TransferStats> {
+    let tree = storage::load_manifest(&storage::snapshot_path(root, &snap.directory)?)?;
+    let store = ObjectStore::open(root)?;
+    let present: BTreeSet<String> = backend
+        .list("objects/")?
+        .into_iter()
+        .filter_map(|k| k.strip_prefix("objects/").map(String::from))
+        .collect();
+
+    let mut stats = TransferStats::default();
+    for hash in object_hashes(&tree) {
+        if present.contains(&hash) {
+            continue;
+        }
+        let len = store.object_len(&hash)?;
+        backend.put_stream(&object_key(&hash), &mut store.open_object(&hash)?, len, &hash)?;
+        stats.objects += 1;
+        stats.bytes += len;
+    }
+
+    // The record goes last, so a snapshot is only listed once all its data is there.
+    let key = free_record_key(snap, backend)?;
+    let record = SnapshotRecord { format_version: FORMAT_VERSION, snapshot: snap.clone(), tree };
+    backend.put(&key, serde_json::to_string_pretty(&record)?.as_bytes())?;
+    Ok(stats)
+}
+
+/// Key for `snap`'s record: the one it already has in the remote, else its id,
+/// suffixed with `-2`, `-3`, ... while another snapshot holds that.
+fn free_record_key(snap: &Snapshot, backend: &dyn Backend) -> Result<String> {
+    let taken: BTreeSet<String> = backend.list("snapshots/")?.into_iter().collect();
+    let id = snapshot_id(snap);
+    for n in 1.. {
+        let key = if n == 1 { record_key(&id) } else { record_key(&format!("{}-{}", id, n)) };
+        if !taken.contains(&key) {
+            return Ok(key);
+        }
+        let same = backend
+            .get(&key)?
+            .and_then(|data| serde_json::from_slice::<SnapshotRecord>(&data).ok())
+            .is_some_and(|r| identity(&r.snapshot) == identity(snap));
+        if same {
+            return Ok(key);
+        }
+    }
+    unreachable!("some suffix is free")
+}
+
+/// Every snapshot in the remote, oldest first.
+pub fn list_snapshots(backend: &dyn Backend) -> Result<Vec<SnapshotRecord>> {
+    let mut records = Vec::new();
+    for key in backend.list("snapshots/")? {
+        if !key.ends_with(".json") {
+            continue;
+        }
+        let Some(data) = backend.get(&key)? else {
+            continue;
+        };
+        let value: serde_json::Value = serde_json::from_slice(&data)
+            .map_err(|e| anyhow!("unreadable snapshot '{}' in {}: {}", key, backend.location(), e))?;
+        let version = storage::migrate::format_version_of(&value);
+        storage::migrate::check_supported_at(version, &format!("snapshot '{}' in {}", key, backend.location()))?;
+        records.push(serde_json::from_value::<SnapshotRecord>(value)?);
+    }
+    records.sort_by_key(|r| r.snapshot.created_at);
+    Ok(records)
+}
+
+/// Fetch the data of a remote snapshot that `store` lacks. Every object is
+/// checked against its hash before it is kept.
+pub fn download_snapshot(store: &ObjectStore, record: &SnapshotRecord, backend: &dyn Backend) -> Result<TransferStats> {
+    let mut stats = TransferStats::default();
+    for hash in object_hashes(&record.tree) {
+        if store.contains(&hash) {
+            continue;
+        }
+        let mut data = backend
+            .open(&object_key(&hash))?
+            .ok_or_else(|| anyhow!("object {} is missing from {}", hash, backend.location()))?;
+        stats.bytes += store.insert_stream(&hash, &mut data)?;
+        stats.objects += 1;
+    }
+    store.finish()?;
+    Ok(stats)
+}
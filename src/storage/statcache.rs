@@ -0,0 +1,213 @@
+// src/storage/statcache.rs
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+use crate::utils::io::write_atomic;
+
+const CACHE_MAGIC: &[u8; 8] = b"GHSTAT1\n";
+/// dev (8) + inode (8) + size (8) + mtime (8) + ctime (8) + sha256 (32)
+const RECORD_LEN: usize = 72;
+
+/// How close to the start of a scan a file may have changed and still be cached.
+/// A write landing in the same timestamp tick as the scan's read would leave the
+/// stat data unchanged, so such files are hashed again next time.
+const RACY_WINDOW_NS: i64 = 2_000_000_000;
+
+/// What identifies a file's content without reading it: size, mtime and ctime (in
+/// nanoseconds) of the inode.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Stat {
+    size: u64,
+    mtime: i64,
+    ctime: i64,
+}
+
+/// Content hashes of the scope's files by (size, mtime, ctime, inode), so a scan
+/// only reads the files that changed since the previous one. Kept as `statcache`
+/// in the data dir; it only ever describes this machine's checkout.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct StatCache {
+    entries: HashMap<(u64, u64), (Stat, [u8; 32])>,
+}
+
+pub fn cache_path(root: &Path) -> Result<PathBuf> {
+    Ok(storage::data_dir(root)?.join("statcache"))
+}
+
+impl StatCache {
+    /// The scope's cache; empty when there is none yet or it cannot be read.
+    pub fn load(root: &Path) -> Self {
+        let data = cache_path(root).ok().and_then(|p| fs::read(p).ok()).unwrap_or_default();
+        let Some(records) = data.strip_prefix(CACHE_MAGIC).filter(|r| r.len() % RECORD_LEN == 0) else {
+            return Self::default();
+        };
+        let u64_at = |r: &[u8], at: usize| u64::from_le_bytes(r[at..at + 8].try_into().unwrap());
+        let entries = records
+            .chunks_exact(RECORD_LEN)
+            .map(|r| {
+                let stat = Stat { size: u64_at(r, 16), mtime: u64_at(r, 24) as i64, ctime: u64_at(r, 32) as i64 };
+                ((u64_at(r, 0), u64_at(r, 8)), (stat, r[40..].try_into().unwrap()))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub fn save(&self, root: &Path) -> Result<()> {
+        let mut data = Vec::with_capacity(CACHE_MAGIC.len() + self.entries.len() * RECORD_LEN);
+        data.extend_from_slice(CACHE_MAGIC);
+        for ((dev, ino), (stat, hash)) in &self.entries {
+            for n in [*dev, *ino, stat.size, stat.mtime as u64, stat.ctime as u64] {
+                data.extend_from_slice(&n.to_le_bytes());
+            }
+            data.extend_from_slice(hash);
+        }
+        write_atomic(&cache_path(root)?, &data)
+    }
+
+    /// Hash recorded for the file `md` describes, if its stat data is unchanged.
+    pub fn hash_of(&self, md: &fs::Metadata) -> Option<String> {
+        let (id, stat) = stat(md)?;
+        self.entries.get(&id).filter(|(s, _)| *s == stat).map(|(_, hash)| hex::encode(hash))
+    }
+
+    /// Remember `hash` for the file `md` describes, unless it changed so close to
+    /// `since` (the scan's start, in nanoseconds) that a later write could go unseen.
+    pub fn insert(&mut self, md: &fs::Metadata, hash: &str, since: i64) {
+        let Some((id, stat)) = stat(md) else {
+            return;
+        };
+        let Some(hash) = hex::decode(hash).ok().and_then(|h| <[u8; 32]>::try_from(h).ok()) else {
+            return;
+        };
+        if stat.mtime < since - RACY_WINDOW_NS && stat.ctime < since - RACY_WINDOW_NS {
+            self.entries.insert(id, (stat, hash));
+        }
+    }
+}
+
+/// Device and inode of a file, and its stat data, on Unix; elsewhere nothing
+/// identifies a file reliably.
+fn stat(md: &fs::Metadata) -> Option<((u64, u64), Stat)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let ns = |s: i64, n: i64| s.checked_mul(1_000_000_000)?.checked_add(n);
+        let stat = Stat { size: md.size(), mtime: ns(md.mtime(), md.mtime_nsec())?, ctime: ns(md.ctime(), md.ctime_nsec())? };
+        Some(((md.dev(), md.ino()), stat))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
+        None
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    const HASH: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    /// A scan start far enough after `md` changed for it to be cached.
+    fn later(md: &fs::Metadata) -> i64 {
+        md.mtime().max(md.ctime()) * 1_000_000_000 + 10 * RACY_WINDOW_NS
+    }
+
+    fn cached(file: &Path) -> (StatCache, fs::Metadata) {
+        let md = fs::metadata(file).unwrap();
+        let mut cache = StatCache::default();
+        cache.insert(&md, HASH, later(&md));
+        (cache, md)
+    }
+
+    #[test]
+    fn hits_on_unchanged_stat_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"hello").unwrap();
+        let (cache, md) = cached(&file);
+        assert_eq!(cache.hash_of(&md).as_deref(), Some(HASH));
+        assert_eq!(cache.hash_of(&fs::metadata(&file).unwrap()).as_deref(), Some(HASH));
+    }
+
+    #[test]
+    fn misses_when_size_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"hello").unwrap();
+        let (cache, md) = cached(&file);
+        fs::write(&file, b"hello, world").unwrap();
+        // Keep mtime as it was, so only the size gives the change away.
+        filetime::set_file_mtime(&file, filetime::FileTime::from_last_modification_time(&md)).unwrap();
+        assert_eq!(cache.hash_of(&fs::metadata(&file).unwrap()), None);
+    }
+
+    #[test]
+    fn misses_when_ctime_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"hello").unwrap();
+        let (cache, md) = cached(&file);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+        let now = fs::metadata(&file).unwrap();
+        assert_eq!((now.size(), now.mtime(), now.mtime_nsec()), (md.size(), md.mtime(), md.mtime_nsec()));
+        assert_ne!((now.ctime(), now.ctime_nsec()), (md.ctime(), md.ctime_nsec()));
+        assert_eq!(cache.hash_of(&now), None);
+    }
+
+    #[test]
+    fn misses_when_inode_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"hello").unwrap();
+        let (cache, md) = cached(&file);
+        // Replaced by a file of the same size and mtime, as an editor's atomic save might.
+        let other = dir.path().join("b");
+        fs::write(&other, b"jello").unwrap();
+        filetime::set_file_mtime(&other, filetime::FileTime::from_last_modification_time(&md)).unwrap();
+        fs::rename(&other, &file).unwrap();
+        let now = fs::metadata(&file).unwrap();
+        assert_ne!(now.ino(), md.ino());
+        assert_eq!(cache.hash_of(&now), None);
+    }
+
+    #[test]
+    fn skips_files_changed_within_the_racy_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"hello").unwrap();
+        let md = fs::metadata(&file).unwrap();
+        let changed = md.mtime() * 1_000_000_000 + md.mtime_nsec();
+
+        let mut cache = StatCache::default();
+        cache.insert(&md, HASH, changed);
+        cache.insert(&md, HASH, changed + RACY_WINDOW_NS);
+        assert_eq!(cache.hash_of(&md), None);
+        assert!(cache == StatCache::default());
+
+        cache.insert(&md, HASH, later(&md));
+        assert_eq!(cache.hash_of(&md).as_deref(), Some(HASH));
+    }
+
+    #[test]
+    fn survives_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(storage::groundhog_dir(dir.path())).unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"hello").unwrap();
+        let (cache, md) = cached(&file);
+        cache.save(dir.path()).unwrap();
+        let loaded = StatCache::load(dir.path());
+        assert!(loaded == cache);
+        assert_eq!(loaded.hash_of(&md).as_deref(), Some(HASH));
+
+        fs::write(cache_path(dir.path()).unwrap(), b"GHSTAT1\ntruncated").unwrap();
+        assert!(StatCache::load(dir.path()) == StatCache::default());
+    }
+}
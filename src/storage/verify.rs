@@ -0,0 +1,145 @@
+// src/storage/verify.rs
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::groundhog::GroundHogConfig;
+use crate::storage::{self, objects::ObjectStore};
+use crate::utils::hash::{flatten_tree, sha256_reader};
+
+/// Health of a single stored object as seen by `verify`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ObjectHealth {
+    Ok,
+    Missing,
+    Corrupt,
+}
+
+/// Per-snapshot result of checking its manifest against the object store.
+pub struct SnapshotHealth {
+    pub name: String,
+    pub scope: String,
+    pub files: usize,
+    /// Paths whose object is absent from the store.
+    pub missing: Vec<String>,
+    /// Paths whose stored object no longer hashes to the manifest hash.
+    pub corrupt: Vec<String>,
+    /// Set when the snapshot directory or its manifest cannot be read.
+    pub error: Option<String>,
+}
+
+impl SnapshotHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none() && self.missing.is_empty() && self.corrupt.is_empty()
+    }
+}
+
+pub struct VerifyReport {
+    pub snapshots: Vec<SnapshotHealth>,
+    /// Directories under the store that no `Snapshot.directory` points to.
+    pub orphaned_dirs: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_healthy(&self) -> bool {
+        self.snapshots.iter().all(SnapshotHealth::is_healthy)
+    }
+}
+
+/// Check every snapshot recorded in `config` against the data on disk.
+/// Each object is re-hashed at most once, however many snapshots share it.
+pub fn verify_store(root: &Path, config: &GroundHogConfig) -> Result<VerifyReport> {
+    let store = ObjectStore::open(root)?;
+    let mut checked: BTreeMap<String, ObjectHealth> = BTreeMap::new();
+    let mut snapshots = Vec::new();
+
+    for snap in &config.snapshots {
+        let mut health = SnapshotHealth {
+            name: snap.name.clone(),
+            scope: snap.scope.clone(),
+            files: 0,
+            missing: Vec::new(),
+            corrupt: Vec::new(),
+            error: None,
+        };
+
+        let snap_dir = storage::snapshot_path(root, &snap.directory)?;
+        if !snap_dir.is_dir() {
+            health.error = Some(format!("snapshot directory '{}' is gone", snap.directory));
+            snapshots.push(health);
+            continue;
+        }
+        let tree = match storage::load_manifest(&snap_dir) {
+            Ok(t) => t,
+            Err(e) => {
+                health.error = Some(format!("unreadable manifest: {}", e));
+                snapshots.push(health);
+                continue;
+            }
+        };
+
+        for (path, node) in flatten_tree(&tree) {
+            if node.is_dir || !node.kind.is_file() {
+                continue;
+            }
+            health.files += 1;
+            // A chunked file is as damaged as its worst chunk.
+            let mut status = ObjectHealth::Ok;
+            for hash in node.object_hashes() {
+                let s = *checked
+                    .entry(hash.to_string())
+                    .or_insert_with(|| check_object(&store, hash));
+                if s != ObjectHealth::Ok {
+                    status = s;
+                    break;
+                }
+            }
+            match status {
+                ObjectHealth::Ok => {}
+                ObjectHealth::Missing => health.missing.push(path),
+                ObjectHealth::Corrupt => health.corrupt.push(path),
+            }
+        }
+        snapshots.push(health);
+    }
+
+    Ok(VerifyReport {
+        snapshots,
+        orphaned_dirs: orphaned_dirs(root, config)?,
+    })
+}
+
+fn check_object(store: &ObjectStore, hash: &str) -> ObjectHealth {
+    let Ok(reader) = store.open_object(hash) else {
+        return ObjectHealth::Missing;
+    };
+    match sha256_reader(reader) {
+        Ok(actual) if actual == hash => ObjectHealth::Ok,
+        _ => ObjectHealth::Corrupt,
+    }
+}
+
+fn orphaned_dirs(root: &Path, config: &GroundHogConfig) -> Result<Vec<PathBuf>> {
+    let store_dir = storage::store_dir(root)?;
+    let data = storage::data_dir(root)?;
+    if !store_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let known: BTreeSet<PathBuf> = config
+        .snapshots
+        .iter()
+        .map(|s| data.join(&s.directory))
+        .collect();
+
+    let mut out = Vec::new();
+    for entry in fs::read_dir(&store_dir)? {
+        let p = entry?.path();
+        if p.is_dir() && !known.contains(&p) {
+            out.push(p);
+        }
+    }
+    out.sort();
+    Ok(out)
+}
@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{Read, Result as IoResult};
+use std::path::Path;
+
+/// Extensions of formats that are already compressed; recompressing them wastes CPU for no gain.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "apk", "br", "bz2", "docx", "flac", "gif", "gz", "jar", "jpeg", "jpg", "lz4", "lzma",
+    "mkv", "mov", "mp3", "mp4", "ogg", "png", "pptx", "rar", "tbz2", "tgz", "txz", "webm", "webp",
+    "whl", "woff", "woff2", "xlsx", "xz", "zip", "zst",
+];
+
+/// Leading magic bytes of common compressed container formats.
+const COMPRESSED_MAGIC: &[&[u8]] = &[
+    &[0x1f, 0x8b],                         // gzip
+    &[0x28, 0xb5, 0x2f, 0xfd],             // zstd
+    &[0xfd, b'7', b'z', b'X', b'Z', 0x00], // xz
+    b"BZh",                                // bzip2
+    b"PK\x03\x04",                         // zip (and docx/jar/...)
+    &[b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c], // 7z
+    &[0x04, 0x22, 0x4d, 0x18],             // lz4 frame
+    b"Rar!\x1a\x07",                       // rar
+    &[0x89, b'P', b'N', b'G'],             // png
+    &[0xff, 0xd8, 0xff],                   // jpeg
+];
+
+/// Whether `path` looks already compressed, by extension or by its leading magic bytes.
+pub fn is_precompressed(path: &Path) -> IoResult<bool> {
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        let ext = ext.to_ascii_lowercase();
+        if COMPRESSED_EXTENSIONS.contains(&ext.as_str()) {
+            return Ok(true);
+        }
+    }
+
+    let mut head = [0u8; 8];
+    let mut file = File::open(path)?;
+    let mut n = 0;
+    while n < head.len() {
+        let read = file.read(&mut head[n..])?;
+        if read == 0 { break; }
+        n += read;
+    }
+    Ok(COMPRESSED_MAGIC.iter().any(|m| head[..n].starts_with(m)))
+}
@@ -0,0 +1,133 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+/// Marks an escaped byte in an encoded name. File names cannot contain NUL, so an
+/// encoded name never collides with a real one and valid UTF-8 names encode as
+/// themselves.
+const ESCAPE: char = '\0';
+
+/// Name as recorded in manifests: the name itself when it is valid UTF-8, otherwise
+/// with every byte that is not part of valid UTF-8 written as NUL and two hex digits.
+pub fn encode_name(name: &OsStr) -> String {
+    #[cfg(unix)]
+    {
+        use std::fmt::Write;
+        use std::os::unix::ffi::OsStrExt;
+        let mut out = String::new();
+        for chunk in name.as_bytes().utf8_chunks() {
+            out.push_str(chunk.valid());
+            for byte in chunk.invalid() {
+                let _ = write!(out, "{}{:02x}", ESCAPE, byte);
+            }
+        }
+        out
+    }
+    // Elsewhere names are Unicode already; what does not convert is replaced.
+    #[cfg(not(unix))]
+    name.to_string_lossy().into_owned()
+}
+
+/// The name `encode_name` was given.
+pub fn decode_name(name: &str) -> OsString {
+    if !name.contains(ESCAPE) {
+        return OsString::from(name);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        let mut bytes = Vec::with_capacity(name.len());
+        let mut rest = name;
+        while let Some(at) = rest.find(ESCAPE) {
+            bytes.extend_from_slice(&rest.as_bytes()[..at]);
+            let hex = rest.get(at + 1..at + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+            match hex {
+                Some(byte) => {
+                    bytes.push(byte);
+                    rest = &rest[at + 3..];
+                }
+                None => {
+                    bytes.push(0);
+                    rest = &rest[at + 1..];
+                }
+            }
+        }
+        bytes.extend_from_slice(rest.as_bytes());
+        OsString::from_vec(bytes)
+    }
+    #[cfg(not(unix))]
+    OsString::from(name)
+}
+
+/// A relative path as recorded in manifests: its encoded components joined by `/`.
+pub fn encode_path(path: &Path) -> String {
+    path.iter().map(encode_name).collect::<Vec<_>>().join("/")
+}
+
+/// The relative path `encode_path` (or a manifest) gives as `path`.
+pub fn decode_path(path: &str) -> PathBuf {
+    path.split('/').map(decode_name).collect()
+}
+
+/// An encoded name or path for messages, escaped bytes shown as `\xNN`.
+pub fn display_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c == ESCAPE {
+            out.push_str("\\x");
+            out.extend(chars.by_ref().take(2));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+
+    fn name(bytes: &[u8]) -> &OsStr {
+        OsStr::from_bytes(bytes)
+    }
+
+    #[test]
+    fn utf8_names_encode_as_themselves() {
+        for n in ["plain.txt", "café", "日本語.md", " lead and trail ", ""] {
+            assert_eq!(encode_name(OsStr::new(n)), n);
+            assert_eq!(decode_name(n), n);
+        }
+    }
+
+    #[test]
+    fn invalid_bytes_round_trip() {
+        for bytes in [&b"caf\xe9"[..], b"\xff", b"\xff\xfe\xfd", b"a\xc3", b"\xe6\x97\xa5\x80\xe6\x9c\xac", b"\xc3\xa9\xe9"] {
+            let encoded = encode_name(name(bytes));
+            assert!(encoded.contains(ESCAPE), "{:?}", encoded);
+            assert_eq!(decode_name(&encoded).as_bytes(), bytes);
+        }
+        assert_eq!(encode_name(name(b"caf\xe9")), "caf\u{0}e9");
+    }
+
+    #[test]
+    fn escape_like_text_round_trips() {
+        // Only NUL marks an escape; text that merely looks like one is left alone.
+        for n in ["\\x41", "\\u0000e9", "%ff", "\\0ff", "x\\00", "\\"] {
+            assert_eq!(encode_name(OsStr::new(n)), n);
+            assert_eq!(decode_name(n), n);
+        }
+        for bytes in [&b"\\x41\xe9"[..], b"\\u0000ff\xff", b"\xe90e9"] {
+            assert_eq!(decode_name(&encode_name(name(bytes))).as_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn paths_round_trip_by_component() {
+        let path = Path::new(name(b"dir\xff/sub/file\xe9.txt"));
+        let encoded = encode_path(path);
+        assert_eq!(encoded, "dir\u{0}ff/sub/file\u{0}e9.txt");
+        assert_eq!(decode_path(&encoded), path);
+        assert_eq!(display_name(&encoded), "dir\\xff/sub/file\\xe9.txt");
+    }
+}
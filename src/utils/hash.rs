@@ -1,10 +1,17 @@
+use rayon::prelude::*;
 use sha2::{Digest, Sha256};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs::{self, File};
-use std::io::{Read, Result as IoResult};
-use std::path::Path;
+use std::io::{self, Read, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::config::groundhog::TreeNode;
+use crate::config::groundhog::{NodeKind, ScopeSettings, SpecialFilePolicy, SymlinkPolicy, TreeNode};
+use crate::storage::statcache::StatCache;
+use crate::utils::encoding::{encode_name, encode_path};
+use crate::utils::io::{file_mode, file_times, file_xattrs, hard_link_id, is_sparse, special_kind, warn};
+use crate::utils::owner::{file_owner, Names};
 
 pub fn hash_password(password: &str) -> String {
     let mut hasher = Sha256::new();
@@ -20,11 +27,15 @@ pub fn verify_password(password: &str, expected_hash: &str) -> bool {
 }
 
 pub fn sha256_file(path: &Path) -> IoResult<String> {
-    let mut file = File::open(path)?;
+    sha256_reader(File::open(path)?)
+}
+
+/// Hash everything readable from `reader` → hex
+pub fn sha256_reader<R: Read>(mut reader: R) -> IoResult<String> {
     let mut hasher = Sha256::new();
     let mut buf = [0u8; 8192];
     loop {
-        let n = file.read(&mut buf)?;
+        let n = reader.read(&mut buf)?;
         if n == 0 { break; }
         hasher.update(&buf[..n]);
     }
@@ -32,7 +43,7 @@ pub fn sha256_file(path: &Path) -> IoResult<String> {
 }
 
 /// Hash arbitrary bytes → hex
-fn sha256_bytes(bytes: &[u8]) -> String {
+pub fn sha256_bytes(bytes: &[u8]) -> String {
     let mut hasher = Sha256::new();
     hasher.update(bytes);
     hex::encode(hasher.finalize())
@@ -40,7 +51,8 @@ fn sha256_bytes(bytes: &[u8]) -> String {
 
 /// Compute a stable directory hash from a map of (name -> child_hash, kind).
 /// Format: "tree\0{name1}:{hash1}:{k1}\n{name2}:{hash2}:{k2}\n..."
-/// where k= "d" for dir, "f" for file. Sorted by name.
+/// where k= "d" for dir, "f" for file, "l" for symlink, "p"/"s"/"c"/"b" for FIFO,
+/// socket, character and block device. Sorted by name.
 fn hash_dir_index(index: &BTreeMap<String, (String, char)>) -> String {
     let mut buf = Vec::new();
     buf.extend_from_slice(b"tree\0");
@@ -55,40 +67,240 @@ fn hash_dir_index(index: &BTreeMap<String, (String, char)>) -> String {
     sha256_bytes(&buf)
 }
 
+/// Kind letter of an entry in `hash_dir_index`.
+fn index_kind(is_dir: bool, kind: NodeKind) -> char {
+    match (is_dir, kind) {
+        (true, _) => 'd',
+        (false, NodeKind::File) => 'f',
+        (false, NodeKind::Symlink) => 'l',
+        (false, NodeKind::Fifo) => 'p',
+        (false, NodeKind::Socket) => 's',
+        (false, NodeKind::CharDevice) => 'c',
+        (false, NodeKind::BlockDevice) => 'b',
+    }
+}
+
+/// Hash of an entry whose hash `h` is extended by its extended attributes.
+/// Format: "xattrs\0{h}\n{name1}={value1}\n{name2}={value2}\n..." (hex values, sorted by name).
+fn hash_with_xattrs(h: &str, xattrs: &BTreeMap<String, String>) -> String {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(b"xattrs\0");
+    buf.extend_from_slice(h.as_bytes());
+    buf.push(b'\n');
+    for (name, value) in xattrs {
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(b'=');
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    }
+    sha256_bytes(&buf)
+}
+
+/// What `build_merkle_tree` records about each entry besides its content.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanOptions {
+    /// Record access times along with modification times.
+    pub atime: bool,
+    /// Record symlinks as links, follow them, or leave them out.
+    pub symlinks: SymlinkPolicy,
+    /// Record extended attributes (and with them ACLs) of files and directories.
+    pub xattrs: bool,
+    /// Record owning user and group names besides their ids.
+    pub owner_names: bool,
+    /// Record FIFOs, sockets and device nodes, or leave them out.
+    pub special_files: SpecialFilePolicy,
+    /// Threads walking and hashing the tree; 0 uses one per CPU.
+    pub jobs: usize,
+    /// Hash every file, even those the stat cache says are unchanged.
+    pub paranoid: bool,
+}
+
+/// Command-line overrides of how a scope is scanned.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanFlags {
+    /// Thread count to use instead of the scope's `jobs` setting.
+    pub jobs: Option<usize>,
+    /// Ignore the stat cache and hash every file.
+    pub paranoid: bool,
+}
+
+impl ScanOptions {
+    /// What a scope with `settings` records.
+    pub fn from_settings(settings: &ScopeSettings) -> Self {
+        Self {
+            atime: settings.record_atime,
+            symlinks: settings.symlinks,
+            xattrs: settings.xattrs,
+            owner_names: settings.record_owner_names,
+            special_files: settings.special_files,
+            jobs: settings.jobs,
+            paranoid: false,
+        }
+    }
+
+    /// What a scope with `settings` records, scanned as `flags` ask.
+    pub fn with_flags(settings: &ScopeSettings, flags: ScanFlags) -> Self {
+        Self { jobs: flags.jobs.unwrap_or(settings.jobs), paranoid: flags.paranoid, ..Self::from_settings(settings) }
+    }
+}
+
 /// Build a Merkle tree for `root`, excluding anything for which `should_skip(path, is_dir)` returns true.
 /// `display_name` is the node name to record (usually "" for root).
-pub fn build_merkle_tree<F>(root: &Path, display_name: String, mut should_skip: F) -> IoResult<TreeNode>
+/// The entries of each directory are scanned in parallel on `options.jobs` threads;
+/// children are sorted before hashing, so the result does not depend on the job count.
+/// Files `cache` has an unchanged entry for are not read (unless `options.paranoid`);
+/// afterwards `cache` holds the entries of the files just scanned.
+pub fn build_merkle_tree<F>(
+    root: &Path,
+    display_name: String,
+    options: ScanOptions,
+    cache: &mut StatCache,
+    should_skip: F,
+) -> IoResult<TreeNode>
 where
-    F: FnMut(&Path, bool) -> bool
+    F: Fn(&Path, bool) -> bool + Sync
 {
-    fn build<F>(abs: &Path, name: String, should_skip: &mut F) -> IoResult<TreeNode>
+    /// Files with more than one link, by inode: their hash and every path they were seen at.
+    type LinkGroups = HashMap<(u64, u64), (String, Vec<PathBuf>)>;
+
+    /// State shared by every thread of a scan.
+    struct Scan {
+        inodes: Mutex<LinkGroups>,
+        names: Mutex<Names>,
+        /// Hashes from the previous scan, and those of this one.
+        cache: StatCache,
+        fresh: Mutex<StatCache>,
+        /// Paths whose extended attributes could not be read, reported once at the end.
+        xattr_errors: Mutex<Vec<(PathBuf, io::Error)>>,
+        /// When this scan started, in nanoseconds since the epoch.
+        started: i64,
+        paranoid: bool,
+    }
+
+    impl Scan {
+        /// Content hash of the file at `abs`, read from disk unless the cache has it.
+        fn hash_file(&self, abs: &Path, md: &fs::Metadata) -> IoResult<String> {
+            let hash = match self.cache.hash_of(md).filter(|_| !self.paranoid) {
+                Some(hash) => hash,
+                None => sha256_file(abs)?,
+            };
+            self.fresh.lock().unwrap_or_else(|e| e.into_inner()).insert(md, &hash, self.started);
+            Ok(hash)
+        }
+    }
+
+    /// `ancestors` are the directories being scanned above `abs`, kept when following
+    /// links could lead back into one of them.
+    fn build<F>(abs: &Path, name: String, options: ScanOptions, ancestors: &[PathBuf], scan: &Scan, should_skip: &F) -> IoResult<Option<TreeNode>>
     where
-        F: FnMut(&Path, bool) -> bool
+        F: Fn(&Path, bool) -> bool + Sync
     {
-        let md = fs::metadata(abs)?;
+        // The scope root itself is always followed.
+        let md = if name.is_empty() { fs::metadata(abs)? } else { fs::symlink_metadata(abs)? };
+        let md = if !md.file_type().is_symlink() {
+            md
+        } else {
+            match options.symlinks {
+                SymlinkPolicy::Preserve => md,
+                SymlinkPolicy::Skip => return Ok(None),
+                SymlinkPolicy::Follow => match fs::metadata(abs) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        warn(format!("skipping symlink '{}' that cannot be followed: {}", abs.display(), e));
+                        return Ok(None);
+                    }
+                },
+            }
+        };
         let is_dir = md.is_dir();
+        // Never opened: reading a FIFO would block until something writes to it.
+        let special = special_kind(&md);
+        if special.is_some() && options.special_files == SpecialFilePolicy::Skip {
+            return Ok(None);
+        }
 
         if should_skip(abs, is_dir) {
             // Represent skipped paths by an empty node with empty hash, so parents can still compute.
-            return Ok(TreeNode { name, hash: String::new(), is_dir, children: if is_dir { Some(Vec::new()) } else { None }});
+            return Ok(Some(TreeNode { name, is_dir, children: if is_dir { Some(Vec::new()) } else { None }, ..Default::default() }));
         }
+        let (mtime, atime) = file_times(&md);
+        let atime = atime.filter(|_| options.atime);
+        let xattrs = if options.xattrs && !md.file_type().is_symlink() {
+            file_xattrs(abs).unwrap_or_else(|e| {
+                scan.xattr_errors.lock().unwrap_or_else(|e| e.into_inner()).push((abs.to_path_buf(), e));
+                None
+            })
+        } else {
+            None
+        };
+        let (uid, gid) = file_owner(&md);
+        let (user, group) = if options.owner_names {
+            let mut names = scan.names.lock().unwrap_or_else(|e| e.into_inner());
+            (uid.and_then(|u| names.user(u)), gid.and_then(|g| names.group(g)))
+        } else {
+            (None, None)
+        };
+        // Everything recorded about an entry besides its content and kind.
+        let meta = TreeNode { mode: file_mode(&md), uid, gid, user, group, mtime, atime, ..Default::default() };
 
-        if !is_dir {
-            let h = sha256_file(abs)?;
-            Ok(TreeNode { name, hash: h, is_dir: false, children: None })
+        if md.file_type().is_symlink() {
+            let target = encode_name(fs::read_link(abs)?.as_os_str());
+            let h = sha256_bytes(target.as_bytes());
+            Ok(Some(TreeNode { name, hash: h, kind: NodeKind::Symlink, target: Some(target), mode: None, ..meta }))
+        } else if let Some((kind, device)) = special {
+            let h = sha256_bytes(format!("special\0{}:{}", index_kind(false, kind), device.unwrap_or(0)).as_bytes());
+            Ok(Some(TreeNode { name, hash: h, kind, device, ..meta }))
+        } else if !is_dir {
+            // Every link to an inode shares its content, so it is only hashed once.
+            let h = match hard_link_id(&md) {
+                Some(id) => {
+                    let known = scan.inodes.lock().unwrap_or_else(|e| e.into_inner()).get(&id).map(|(h, _)| h.clone());
+                    // Hashed without holding the lock; two links racing here hash the same content.
+                    let hash = match known {
+                        Some(hash) => hash,
+                        None => scan.hash_file(abs, &md)?,
+                    };
+                    let mut inodes = scan.inodes.lock().unwrap_or_else(|e| e.into_inner());
+                    let (hash, paths) = inodes.entry(id).or_insert((hash, Vec::new()));
+                    paths.push(abs.to_path_buf());
+                    hash.clone()
+                }
+                None => scan.hash_file(abs, &md)?,
+            };
+            let (h, content_hash) = match &xattrs {
+                Some(attrs) => (hash_with_xattrs(&h, attrs), Some(h)),
+                None => (h, None),
+            };
+            Ok(Some(TreeNode { name, hash: h, size: Some(md.len()), sparse: is_sparse(&md), xattrs, content_hash, ..meta }))
         } else {
-            // Build children, skip empty nodes
-            let mut entries: Vec<(String, TreeNode)> = Vec::new();
-            for entry in fs::read_dir(abs)? {
-                let entry = entry?;
-                let p = entry.path();
-                let n = entry.file_name().to_string_lossy().to_string();
-                let node = build(&p, n.clone(), should_skip)?;
-                // skip nodes with empty hash only if they are empty-skip placeholders
-                if !(node.hash.is_empty() && node.is_dir && node.children.as_ref().map(|c| c.is_empty()).unwrap_or(true)) {
-                    entries.push((n, node));
+            let inner;
+            let ancestors = if options.symlinks == SymlinkPolicy::Follow {
+                let dir = fs::canonicalize(abs)?;
+                if ancestors.contains(&dir) {
+                    warn(format!("skipping '{}': it links back into a directory containing it", abs.display()));
+                    return Ok(None);
                 }
-            }
+                inner = [ancestors, &[dir]].concat();
+                &inner[..]
+            } else {
+                ancestors
+            };
+
+            // Build children in parallel, skip empty nodes
+            let dir_entries = fs::read_dir(abs)?.collect::<IoResult<Vec<_>>>()?;
+            let built = dir_entries
+                .par_iter()
+                .map(|entry| {
+                    let n = encode_name(&entry.file_name());
+                    Ok(build(&entry.path(), n.clone(), options, ancestors, scan, should_skip)?.map(|node| (n, node)))
+                })
+                .collect::<IoResult<Vec<_>>>()?;
+            let mut entries: Vec<(String, TreeNode)> = built
+                .into_iter()
+                .flatten()
+                // skip nodes with empty hash only if they are empty-skip placeholders
+                .filter(|(_, node)| !(node.hash.is_empty() && node.is_dir && node.children.as_ref().map(|c| c.is_empty()).unwrap_or(true)))
+                .collect();
             // sort by name for stable hashing
             entries.sort_by(|a, b| a.0.cmp(&b.0));
 
@@ -96,25 +308,78 @@ where
             let mut index: BTreeMap<String, (String, char)> = BTreeMap::new();
             let mut kids: Vec<TreeNode> = Vec::with_capacity(entries.len());
             for (n, node) in entries {
-                index.insert(n.clone(), (node.hash.clone(), if node.is_dir { 'd' } else { 'f' }));
+                index.insert(n.clone(), (node.hash.clone(), index_kind(node.is_dir, node.kind)));
                 kids.push(node);
             }
-            let h = hash_dir_index(&index);
-            Ok(TreeNode { name, hash: h, is_dir: true, children: Some(kids) })
+            let mut h = hash_dir_index(&index);
+            if let Some(attrs) = &xattrs {
+                h = hash_with_xattrs(&h, attrs);
+            }
+            Ok(Some(TreeNode { name, hash: h, is_dir: true, xattrs, children: Some(kids), ..meta }))
         }
     }
 
-    build(root, display_name, &mut should_skip)
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(options.jobs).build().map_err(io::Error::other)?;
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or(0);
+    let scan = Scan {
+        inodes: Mutex::default(),
+        names: Mutex::default(),
+        cache: std::mem::take(cache),
+        fresh: Mutex::default(),
+        xattr_errors: Mutex::default(),
+        started,
+        paranoid: options.paranoid,
+    };
+    let mut root_node = pool.install(|| build(root, display_name, options, &[], &scan, &should_skip))?.unwrap_or_default();
+    *cache = scan.fresh.into_inner().unwrap_or_else(|e| e.into_inner());
+    let xattr_errors = scan.xattr_errors.into_inner().unwrap_or_else(|e| e.into_inner());
+    if let Some((path, e)) = xattr_errors.first() {
+        warn(format!(
+            "could not read extended attributes of {} path(s), e.g. '{}': {}; they are not recorded",
+            xattr_errors.len(),
+            path.display(),
+            e
+        ));
+    }
+
+    // Within each group of hard links, the first path leads and the others link to it.
+    let inodes = scan.inodes.into_inner().unwrap_or_else(|e| e.into_inner());
+    for (_, paths) in inodes.into_values() {
+        let mut paths: Vec<String> = paths
+            .iter()
+            .filter_map(|p| p.strip_prefix(root).ok())
+            .map(encode_path)
+            .collect();
+        if paths.len() < 2 {
+            continue;
+        }
+        paths.sort();
+        for member in &paths[1..] {
+            if let Some(node) = node_at_mut(&mut root_node, member) {
+                node.hard_link = Some(paths[0].clone());
+            }
+        }
+    }
+    Ok(root_node)
+}
+
+/// The node at `path` (relative, `/`-separated) inside `tree`.
+fn node_at_mut<'a>(tree: &'a mut TreeNode, path: &str) -> Option<&'a mut TreeNode> {
+    let mut node = tree;
+    for part in path.split('/') {
+        node = node.children.as_mut()?.iter_mut().find(|c| c.name == part)?;
+    }
+    Some(node)
 }
 
-/// Flatten a tree into a map path -> (hash, is_dir). Paths are slash-separated relative paths (no leading slash).
-pub fn flatten_tree(tree: &TreeNode) -> BTreeMap<String, (String, bool)> {
+/// Flatten a tree into a map path -> node. Paths are slash-separated relative paths (no leading slash).
+pub fn flatten_tree(tree: &TreeNode) -> BTreeMap<String, &TreeNode> {
     let mut out = BTreeMap::new();
     let mut q: VecDeque<(String, &TreeNode)> = VecDeque::new();
     q.push_back(("".into(), tree));
 
     while let Some((prefix, node)) = q.pop_front() {
-        let path = if prefix.is_empty() || node.name.is_empty() {
+        let path = if node.name.is_empty() {
             // root
             "".to_string()
         } else if prefix.is_empty() {
@@ -124,14 +389,14 @@ pub fn flatten_tree(tree: &TreeNode) -> BTreeMap<String, (String, bool)> {
         };
 
         if !(path.is_empty()) {
-            out.insert(path.clone(), (node.hash.clone(), node.is_dir));
+            out.insert(path.clone(), node);
         }
-        if node.is_dir {
-            if let Some(children) = node.children.as_ref() {
-                let child_prefix = path;
-                for child in children {
-                    q.push_back((child_prefix.clone(), child));
-                }
+        if node.is_dir
+            && let Some(children) = node.children.as_ref()
+        {
+            let child_prefix = path;
+            for child in children {
+                q.push_back((child_prefix.clone(), child));
             }
         }
     }
@@ -148,28 +413,46 @@ pub struct Diff {
 /// Compare two trees (left = current, right = baseline) and return changes to turn baseline→current.
 /// - added: present in current, absent in baseline
 /// - deleted: present in baseline, absent in current
-/// - modified: present in both but hashes differ (file content or subtree changed)
+/// - modified: present in both but hashes differ (file content or subtree changed),
+///   or permissions differ where both trees recorded them; "" when the root's own do
 pub fn diff_trees(current: &TreeNode, baseline: &TreeNode) -> Diff {
     let mut d = Diff::default();
+    if mode_changed(baseline, current) {
+        d.modified.push(String::new());
+    }
     let a = flatten_tree(current);
     let b = flatten_tree(baseline);
 
     // additions + modifications
-    for (path, (h, _is_dir)) in &a {
+    for (path, node) in &a {
         match b.get(path) {
             None => d.added.push(path.clone()),
-            Some((h_old, _)) => {
-                if h_old != h {
+            Some(old) => {
+                if old.hash != node.hash || old.kind != node.kind || old.hard_link != node.hard_link || mode_changed(old, node) || owner_changed(old, node) {
                     d.modified.push(path.clone());
                 }
             }
         }
     }
     // deletions
-    for (path, _) in &b {
+    for path in b.keys() {
         if !a.contains_key(path) {
             d.deleted.push(path.clone());
         }
     }
     d
-}
\ No newline at end of file
+}
+/// Owners only count when both sides recorded them, by name if both have names.
+fn owner_changed(a: &TreeNode, b: &TreeNode) -> bool {
+    fn differs<T: PartialEq>(x: &Option<T>, y: &Option<T>) -> bool {
+        matches!((x, y), (Some(x), Some(y)) if x != y)
+    }
+    let user = if a.user.is_some() && b.user.is_some() { differs(&a.user, &b.user) } else { differs(&a.uid, &b.uid) };
+    let group = if a.group.is_some() && b.group.is_some() { differs(&a.group, &b.group) } else { differs(&a.gid, &b.gid) };
+    user || group
+}
+
+/// Modes only count when both sides recorded one; older manifests have none.
+fn mode_changed(a: &TreeNode, b: &TreeNode) -> bool {
+    matches!((a.mode, b.mode), (Some(x), Some(y)) if x != y)
+}
@@ -1,10 +1,19 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use indicatif::ProgressBar;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::path::Path;
-use walkdir::WalkDir;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use colored::*;
+use filetime::FileTime;
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
 
+use crate::config::groundhog::{NodeKind, ScopeSettings, SymlinkPolicy, TreeNode};
+use crate::storage::objects::{ObjectStore, CHUNKING_THRESHOLD};
+use crate::utils::encoding::{decode_name, decode_path, encode_name};
+use crate::utils::hash::flatten_tree;
+use crate::utils::owner::{can_chown, set_owner, Names};
+
 /// Load a .groundhogignore matcher from a scope root, if present.
 fn load_groundhogignore(root: &Path) -> Option<Gitignore> {
     let ignore_file = root.join(".groundhogignore");
@@ -18,175 +27,545 @@ fn load_groundhogignore(root: &Path) -> Option<Gitignore> {
     None
 }
 
-/// Recursively copy directory `from` → `to`, excluding files matched by .groundhogignore
-/// plus any names in `exclude_names`.
-pub fn copy_dir_recursive_excluding(
-    from: &Path,
-    to: &Path,
-    bar: &ProgressBar,
-    exclude_names: &[&str],
-) -> Result<()> {
-    if !to.exists() {
-        fs::create_dir_all(to)?;
-    }
-
-    let gitignore = load_groundhogignore(from);
-    let root = from.to_path_buf();
-
-    let should_include = |e: &walkdir::DirEntry| {
-        let name = match e.file_name().to_str() {
-            Some(n) => n,
-            None => return true,
-        };
+pub fn make_skipper(root: &Path) -> impl Fn(&Path, bool) -> bool + Sync {
+    let gitignore = super::io::load_groundhogignore(root);
+    let root = root.to_path_buf();
 
-        // Always exclude explicit names (e.g. ".groundhog")
-        if exclude_names.iter().any(|ex| name.eq_ignore_ascii_case(ex)) {
-            return false;
-        }
+    move |path: &Path, is_dir: bool| {
+        if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
+            // Skip `.groundhog` and `.groundhogignore` everywhere
+            if name.eq_ignore_ascii_case(".groundhog") || name.eq_ignore_ascii_case(".groundhogignore") {
+                return true;
+            }
 
-        // Special case: exclude `manifest.json` only at the root
-        if name.eq_ignore_ascii_case("manifest.json") {
-            if let Ok(rel) = e.path().strip_prefix(&root) {
-                if rel.components().count() == 1 {
-                    return false; // only root-level manifest.json
-                }
+            // Skip `manifest.json` only at root
+            if name.eq_ignore_ascii_case("manifest.json")
+                && let Ok(rel) = path.strip_prefix(&root)
+                && rel.components().count() == 1
+            {
+                return true;
             }
         }
 
-        // Apply .groundhogignore matcher if present
-        if let Some(ref gi) = gitignore {
-            let path = e.path();
-            if gi.matched_path_or_any_parents(path, e.file_type().is_dir()).is_ignore() {
-                return false;
-            }
+        // Apply ignore patterns if available
+        if let Some(ref gi) = gitignore
+            && gi.matched_path_or_any_parents(path, is_dir).is_ignore()
+        {
+            return true;
         }
 
-        true
-    };
+        false
+    }
+}
 
-    for entry in WalkDir::new(from).into_iter().filter_entry(|e| should_include(e)) {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-        let path = entry.path();
-        let rel = match path.strip_prefix(from) {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
-        let dest = to.join(rel);
+/// Copy every file in `tree` from `root` into the object store; objects already present are skipped.
+/// Files of at least `CHUNKING_THRESHOLD` bytes are stored as content-defined chunks, and the
+/// chunk list is recorded on their node so the manifest can reassemble them. Contents
+/// in `known_chunks` (content hash -> chunk list) whose chunks are all stored are not read.
+pub fn copy_tree_files(
+    root: &Path,
+    store: &ObjectStore,
+    tree: &mut TreeNode,
+    known_chunks: &HashMap<String, Vec<String>>,
+    bar: &ProgressBar,
+) -> Result<()> {
+    let path = root.join(decode_name(&tree.name));
+    if tree.is_dir {
+        for child in tree.children.iter_mut().flatten() {
+            copy_tree_files(&path, store, child, known_chunks, bar)?;
+        }
+    } else if tree.kind.is_file() && !tree.hash.is_empty() {
+        // Skipping it would commit a manifest naming content the store never got.
+        if !fs::metadata(&path).is_ok_and(|m| m.is_file()) {
+            return Err(anyhow!("'{}' changed while the snapshot was being taken", path.display()));
+        }
+        if tree.size.unwrap_or(0) >= CHUNKING_THRESHOLD {
+            tree.chunks = match known_chunks.get(tree.content_hash()) {
+                Some(chunks) if chunks.iter().all(|c| store.contains(c)) => Some(chunks.clone()),
+                _ => Some(store.insert_chunked(&path, tree.content_hash())?),
+            };
+        } else {
+            store.insert_file(&path, tree.content_hash())?;
+        }
+        bar.inc(1);
+    }
+    Ok(())
+}
 
-        if entry.file_type().is_dir() {
+/// Restore selected paths from the object store into `root`.
+/// `tree` is the flattened snapshot manifest the paths are looked up in. Unless the
+/// scope follows symlinks, a link in the way of a restored entry is replaced rather
+/// than written through. Hard-linked files are linked to their group's first file
+/// once everything else is in place. Owners, extended attributes and special files
+/// that cannot be restored are reported in a warning.
+pub fn restore_selected_files(
+    store: &ObjectStore,
+    tree: &BTreeMap<String, &TreeNode>,
+    root: &Path,
+    paths: &[String],
+    settings: &ScopeSettings,
+    bar: &ProgressBar,
+) -> Result<()> {
+    let follow = settings.symlinks == SymlinkPolicy::Follow;
+    let mut extras = Extras::new(settings);
+    // Directory modes are applied last: a read-only directory would refuse its own children.
+    let mut dir_modes = Vec::new();
+    let mut hard_links = Vec::new();
+    for rel in paths {
+        let Some(node) = tree.get(rel) else { continue };
+        let dest = root.join(decode_path(rel));
+        let existing = fs::symlink_metadata(&dest).ok();
+        let is_link = existing.as_ref().is_some_and(|m| m.file_type().is_symlink());
+        if is_link && (!follow || node.kind == NodeKind::Symlink) {
+            fs::remove_file(&dest)?;
+        }
+        if node.kind == NodeKind::Symlink {
+            if existing.is_some_and(|m| m.is_dir()) {
+                fs::remove_dir_all(&dest)?;
+            } else if dest.is_file() {
+                fs::remove_file(&dest)?;
+            }
+            make_symlink(Path::new(&decode_name(node.target.as_deref().unwrap_or_default())), &dest)?;
+            extras.apply(&dest, node);
+            set_times(&dest, node)?;
+            bar.inc(1);
+        } else if node.kind.is_special() {
+            match existing {
+                Some(m) if m.is_dir() => fs::remove_dir_all(&dest)?,
+                Some(m) if !m.file_type().is_symlink() => fs::remove_file(&dest)?,
+                _ => {}
+            }
+            match make_special(&dest, node) {
+                Ok(()) => {
+                    extras.apply(&dest, node);
+                    set_mode(&dest, node.mode)?;
+                    set_times(&dest, node)?;
+                }
+                Err(e) => extras.specials.push((dest, e)),
+            }
+            bar.inc(1);
+        } else if node.is_dir {
+            if dest.is_file() {
+                fs::remove_file(&dest)?;
+            }
             fs::create_dir_all(&dest)?;
-        } else if entry.file_type().is_file() {
-            if let Some(parent) = dest.parent() {
-                fs::create_dir_all(parent)?;
+            if let Some(mode) = node.mode {
+                set_mode(&dest, Some(mode | 0o700))?;
+            }
+            extras.apply(&dest, node);
+            if node.mode.is_some() {
+                dir_modes.push((dest, node.mode));
             }
-            fs::copy(path, &dest)?;
+        } else if let Some(leader) = &node.hard_link {
+            hard_links.push((root.join(decode_path(leader)), dest, *node));
+        } else {
+            restore_file(store, node, &dest, &mut extras)?;
             bar.inc(1);
         }
     }
+    for (leader, dest, node) in hard_links {
+        let linked = |a: &Path, b: &Path| match (fs::metadata(a), fs::symlink_metadata(b)) {
+            (Ok(a), Ok(b)) => hard_link_id(&a).is_some() && hard_link_id(&a) == hard_link_id(&b),
+            _ => false,
+        };
+        if !linked(&leader, &dest) {
+            match fs::symlink_metadata(&dest) {
+                Ok(m) if m.is_dir() => fs::remove_dir_all(&dest)?,
+                Ok(_) => fs::remove_file(&dest)?,
+                Err(_) => {}
+            }
+            // Without its leader (or across devices) the file is restored on its own.
+            if fs::hard_link(&leader, &dest).is_err() {
+                restore_file(store, node, &dest, &mut extras)?;
+            }
+        }
+        bar.inc(1);
+    }
+    for (dest, mode) in dir_modes.iter().rev() {
+        set_mode(dest, *mode)?;
+    }
+    extras.report();
     Ok(())
 }
 
-/// Clean a directory except for explicitly listed names.
-pub fn clean_dir_except(dir: &Path, except: &[&str]) -> Result<()> {
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        let keep = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .map(|n| except.contains(&n))
-            .unwrap_or(false);
+/// Restores what a rollback carries on without: owners, extended attributes and
+/// special files. What fails is collected and reported once at the end.
+struct Extras<'a> {
+    settings: &'a ScopeSettings,
+    /// Whether owners are restored at all; without the privileges for it they are left alone.
+    chown: bool,
+    names: Names,
+    owners: Vec<(PathBuf, std::io::Error)>,
+    xattrs: Vec<(PathBuf, std::io::Error)>,
+    specials: Vec<(PathBuf, std::io::Error)>,
+}
 
-        if keep {
-            continue;
+impl<'a> Extras<'a> {
+    fn new(settings: &'a ScopeSettings) -> Self {
+        Self {
+            settings,
+            chown: can_chown(),
+            names: Names::default(),
+            owners: Vec::new(),
+            xattrs: Vec::new(),
+            specials: Vec::new(),
         }
+    }
 
-        if path.is_dir() {
-            fs::remove_dir_all(&path)?;
-        } else {
-            fs::remove_file(&path)?;
+    /// Apply the owner, then the extended attributes (which a change of owner may clear).
+    fn apply(&mut self, path: &Path, node: &TreeNode) {
+        if self.chown
+            && let Err(e) = set_owner(path, node, &mut self.names)
+        {
+            self.owners.push((path.to_path_buf(), e));
+        }
+        if self.settings.xattrs
+            && node.kind.is_file()
+            && let Err(e) = set_xattrs(path, node)
+        {
+            self.xattrs.push((path.to_path_buf(), e));
         }
     }
 
-    Ok(())
+    fn report(&self) {
+        if let Some((path, e)) = self.specials.first() {
+            warn(format!(
+                "could not recreate {} special file(s), e.g. '{}': {}",
+                self.specials.len(),
+                path.display(),
+                e
+            ));
+        }
+        if let Some((path, e)) = self.owners.first() {
+            warn(format!(
+                "could not restore the owner of {} path(s), e.g. '{}': {}{}",
+                self.owners.len(),
+                path.display(),
+                e,
+                if e.kind() == std::io::ErrorKind::PermissionDenied { " (run as root to restore owners)" } else { "" }
+            ));
+        }
+        if let Some((path, e)) = self.xattrs.first() {
+            warn(format!(
+                "could not restore extended attributes of {} path(s), e.g. '{}': {}",
+                self.xattrs.len(),
+                path.display(),
+                e
+            ));
+        }
+    }
 }
 
-/// Special case: copy everything except the .groundhog directory itself.
-/// Still respects .groundhogignore.
-/// Note: manifest.json is excluded only at root.
-pub fn copy_dir_excluding_groundhog(from: &Path, to: &Path, bar: &ProgressBar) -> Result<()> {
-    copy_dir_recursive_excluding(from, to, bar, &[".groundhog", ".groundhogignore"])
+/// Print a warning that does not stop the command.
+pub fn warn(message: String) {
+    eprintln!("{} {}: {}", "!".yellow().bold(), "Warning".yellow(), message);
 }
 
-/// Special case: clean directory except .groundhog folder (rollback safety).
-pub fn clean_dir_except_groundhog(from: &Path) -> Result<()> {
-    clean_dir_except(from, &[".groundhog", ".groundhogignore"])
+/// Write the content of the file `node` to `dest`, with its metadata.
+fn restore_file(store: &ObjectStore, node: &TreeNode, dest: &Path, extras: &mut Extras) -> Result<()> {
+    // A read-only file cannot be opened for writing, and writing to a file with other
+    // hard links would change them too, so such files are replaced instead.
+    if fs::symlink_metadata(dest).is_ok_and(|m| m.is_file() && (m.permissions().readonly() || hard_link_id(&m).is_some())) {
+        fs::remove_file(dest)?;
+    }
+    match &node.chunks {
+        Some(chunks) => store.restore_chunks(chunks, dest, node.sparse)?,
+        None => store.restore_file(node.content_hash(), dest, node.sparse)?,
+    }
+    // Before the mode: a change of owner clears set-id bits, and a read-only file
+    // takes no extended attributes.
+    extras.apply(dest, node);
+    set_mode(dest, node.mode)?;
+    set_times(dest, node)
 }
 
-pub fn make_skipper(root: &Path) -> impl FnMut(&Path, bool) -> bool {
-    let gitignore = super::io::load_groundhogignore(root);
-    let root = root.to_path_buf();
+/// Create a symlink at `link` pointing to `target`. On Windows, whether it is a
+/// directory link is decided by what `target` currently resolves to.
+fn make_symlink(target: &Path, link: &Path) -> Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, link)?;
+    #[cfg(windows)]
+    {
+        let resolved = link.parent().map(|p| p.join(target)).unwrap_or_else(|| target.to_path_buf());
+        if resolved.is_dir() {
+            std::os::windows::fs::symlink_dir(target, link)?;
+        } else {
+            std::os::windows::fs::symlink_file(target, link)?;
+        }
+    }
+    Ok(())
+}
 
-    move |path: &Path, is_dir: bool| {
-        if let Some(name) = path.file_name().and_then(|s| s.to_str()) {
-            // Skip `.groundhog` and `.groundhogignore` everywhere
-            if name.eq_ignore_ascii_case(".groundhog") || name.eq_ignore_ascii_case(".groundhogignore") {
-                return true;
+/// Extended attributes of `path`, POSIX ACLs included, with hex-encoded values;
+/// `None` when it has none. Fails when they cannot be listed, e.g. because the
+/// filesystem does not support them.
+pub fn file_xattrs(path: &Path) -> std::io::Result<Option<BTreeMap<String, String>>> {
+    #[cfg(unix)]
+    {
+        let mut attrs = BTreeMap::new();
+        for name in xattr::list(path)? {
+            if let Ok(Some(value)) = xattr::get(path, &name) {
+                attrs.insert(encode_name(&name), hex::encode(value));
             }
+        }
+        Ok((!attrs.is_empty()).then_some(attrs))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(None)
+    }
+}
 
-            // Skip `manifest.json` only at root
-            if name.eq_ignore_ascii_case("manifest.json") {
-                if let Ok(rel) = path.strip_prefix(&root) {
-                    if rel.components().count() == 1 {
-                        return true;
-                    }
-                }
+/// Give `path` exactly the extended attributes recorded on `node`. Those the system
+/// manages itself (`security.*`, such as SELinux labels) are set but never removed.
+pub fn set_xattrs(path: &Path, node: &TreeNode) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let recorded = node.xattrs.clone().unwrap_or_default();
+        let present: Vec<_> = match xattr::list(path) {
+            Ok(names) => names.collect(),
+            // Nothing to restore on a filesystem without extended attributes is fine.
+            Err(_) if recorded.is_empty() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for name in present {
+            let key = encode_name(&name);
+            if !recorded.contains_key(&key) && !key.starts_with("security.") {
+                xattr::remove(path, &name)?;
             }
         }
-
-        // Apply ignore patterns if available
-        if let Some(ref gi) = gitignore {
-            if gi.matched_path_or_any_parents(path, is_dir).is_ignore() {
-                return true;
+        for (name, value) in &recorded {
+            let value = hex::decode(value).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            let name = decode_name(name);
+            if xattr::get(path, &name)?.as_deref() != Some(&value[..]) {
+                xattr::set(path, &name, &value)?;
             }
         }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, node);
+    Ok(())
+}
+
+/// Permission bits of a file or directory, on Unix.
+pub fn file_mode(md: &fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(md.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
+        None
+    }
+}
+
+/// Device and inode of a file that has other hard links, on Unix.
+pub fn hard_link_id(md: &fs::Metadata) -> Option<(u64, u64)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        (md.is_file() && md.nlink() > 1).then(|| (md.dev(), md.ino()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
+        None
+    }
+}
 
+/// Kind (and device number, for devices) of a FIFO, socket or device node.
+pub fn special_kind(md: &fs::Metadata) -> Option<(NodeKind, Option<u64>)> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+        let t = md.file_type();
+        if t.is_fifo() {
+            Some((NodeKind::Fifo, None))
+        } else if t.is_socket() {
+            Some((NodeKind::Socket, None))
+        } else if t.is_char_device() {
+            Some((NodeKind::CharDevice, Some(md.rdev())))
+        } else if t.is_block_device() {
+            Some((NodeKind::BlockDevice, Some(md.rdev())))
+        } else {
+            None
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
+        None
+    }
+}
+
+/// Whether a file has holes: fewer bytes allocated than its length.
+pub fn is_sparse(md: &fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        md.is_file() && md.blocks() * 512 < md.len()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
         false
     }
 }
 
-/// Copy only selected files (relative paths from `flatten_tree`) from `from` → `to`.
-pub fn copy_selected_files(from: &Path, to: &Path, files: &[String], bar: &ProgressBar) -> Result<()> {
-    for rel in files {
-        let src = from.join(rel);
-        let dest = to.join(rel);
+/// Create the FIFO, socket or device node `node` at `path`.
+fn make_special(path: &Path, node: &TreeNode) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let file_type = match node.kind {
+            NodeKind::Fifo => libc::S_IFIFO,
+            NodeKind::Socket => libc::S_IFSOCK,
+            NodeKind::CharDevice => libc::S_IFCHR,
+            NodeKind::BlockDevice => libc::S_IFBLK,
+            _ => return Err(std::io::ErrorKind::InvalidInput.into()),
+        };
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+        let mode = file_type | node.mode.unwrap_or(0o644) as libc::mode_t;
+        let device = node.device.unwrap_or(0) as libc::dev_t;
+        if unsafe { libc::mknod(c_path.as_ptr(), mode, device) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, node);
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
 
-        if let Some(parent) = dest.parent() {
-            fs::create_dir_all(parent)?;
+/// Modification and access time of a file or directory, in nanoseconds since the Unix epoch.
+pub fn file_times(md: &fs::Metadata) -> (Option<i64>, Option<i64>) {
+    let ns = |t: FileTime| t.unix_seconds().checked_mul(1_000_000_000)?.checked_add(t.nanoseconds() as i64);
+    (ns(FileTime::from_last_modification_time(md)), ns(FileTime::from_last_access_time(md)))
+}
+
+/// Set the times recorded on `node` back on `path`; whichever is missing is left alone.
+/// For a symlink node the times go on the link itself. Nodes other than files and
+/// directories are never opened for this: opening a FIFO blocks.
+pub fn set_times(path: &Path, node: &TreeNode) -> Result<()> {
+    let time = |ns: i64| FileTime::from_unix_time(ns.div_euclid(1_000_000_000), ns.rem_euclid(1_000_000_000) as u32);
+    if !node.kind.is_file() {
+        if node.mtime.is_none() && node.atime.is_none() {
+            return Ok(());
         }
+        let md = fs::symlink_metadata(path)?;
+        let atime = node.atime.map(time).unwrap_or_else(|| FileTime::from_last_access_time(&md));
+        let mtime = node.mtime.map(time).unwrap_or_else(|| FileTime::from_last_modification_time(&md));
+        filetime::set_symlink_file_times(path, atime, mtime)?;
+        return Ok(());
+    }
+    match (node.atime.map(time), node.mtime.map(time)) {
+        (Some(atime), Some(mtime)) => filetime::set_file_times(path, atime, mtime)?,
+        (None, Some(mtime)) => filetime::set_file_mtime(path, mtime)?,
+        (Some(atime), None) => filetime::set_file_atime(path, atime)?,
+        (None, None) => {}
+    }
+    Ok(())
+}
 
-        if src.is_file() {
-            fs::copy(&src, &dest)?;
-            bar.inc(1);
-        } else if src.is_dir() {
-            fs::create_dir_all(&dest)?;
+/// Set the recorded times of the directories `dirs` (paths in `tree`, "" for its
+/// root) back, deepest first, once restoring their contents has bumped them.
+pub fn restore_dir_times(root: &Path, tree: &TreeNode, dirs: &BTreeSet<String>) -> Result<()> {
+    let nodes = flatten_tree(tree);
+    for rel in dirs.iter().rev() {
+        let node = if rel.is_empty() { Some(tree) } else { nodes.get(rel).copied() };
+        if let Some(node) = node.filter(|n| n.is_dir) {
+            set_times(&root.join(decode_path(rel)), node)?;
         }
     }
     Ok(())
 }
 
+/// Apply recorded permission bits to `path`; nothing happens without them or off Unix.
+pub fn set_mode(path: &Path, mode: Option<u32>) -> Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
 /// Delete only the selected paths (relative) inside `root`.
 pub fn delete_selected_paths(root: &Path, paths: &[String]) -> Result<()> {
+    // Links are removed themselves, never what they point to.
+    fn remove(p: &Path) -> std::io::Result<()> {
+        if fs::symlink_metadata(p)?.is_dir() { fs::remove_dir_all(p) } else { fs::remove_file(p) }
+    }
+
     for rel in paths {
-        let p = root.join(rel);
-        if p.is_file() {
-            let _ = fs::remove_file(&p);
-        } else if p.is_dir() {
-            let _ = fs::remove_dir_all(&p);
+        let p = root.join(decode_path(rel));
+        if fs::symlink_metadata(&p).is_err() {
+            continue;
+        }
+        if let Err(e) = remove(&p)
+            && e.kind() == std::io::ErrorKind::PermissionDenied
+            && let Some(parent) = p.parent()
+            && let Ok(md) = fs::metadata(parent)
+        {
+            // A read-only directory keeps its entries; open it up just for the removal.
+            let mode = file_mode(&md);
+            set_mode(parent, mode.map(|m| m | 0o700))?;
+            let _ = remove(&p);
+            set_mode(parent, mode)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace `path` with `contents` so that readers (and a crash at any point) see either
+/// the old file or the complete new one, never a torn write: the data goes to a sibling
+/// temp file, is fsynced, then renamed over `path`, and the directory entry is synced.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    write_atomic_from(path, &mut &contents[..]).map(|_| ())
+}
+
+/// `write_atomic`, with the contents streamed from `data`. Returns how many bytes it gave.
+pub fn write_atomic_from(path: &Path, data: &mut dyn Read) -> Result<u64> {
+    let dir = path.parent().ok_or_else(|| anyhow!("invalid path '{}'", path.display()))?;
+    fs::create_dir_all(dir)?;
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = dir.join(tmp_name);
+
+    let mut file = fs::File::create(&tmp)?;
+    let written = std::io::copy(data, &mut file)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp, path)?;
+
+    // Persist the rename itself; directories cannot be opened this way on Windows.
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(written)
+}
+
+/// Recursively copy everything inside `src` into `dest` (created if needed),
+/// leaving out top-level entries whose name `skip` matches. Files are synced.
+pub fn copy_dir_contents(src: &Path, dest: &Path, skip: &dyn Fn(&str) -> bool) -> Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        if skip(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_contents(&entry.path(), &target, &|_| false)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+            fs::File::open(&target)?.sync_all()?;
         }
     }
     Ok(())
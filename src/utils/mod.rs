@@ -1,4 +1,5 @@
+pub mod compress;
 pub mod hash;
 pub mod io;
-
-
+pub mod owner;
+pub mod encoding;
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::groundhog::TreeNode;
+
+/// Caches user and group lookups, each of which reads the system's databases.
+#[derive(Default)]
+pub struct Names {
+    users: HashMap<u32, Option<String>>,
+    groups: HashMap<u32, Option<String>>,
+    uids: HashMap<String, Option<u32>>,
+    gids: HashMap<String, Option<u32>>,
+}
+
+impl Names {
+    /// Name of the user `uid`.
+    pub fn user(&mut self, uid: u32) -> Option<String> {
+        self.users.entry(uid).or_insert_with(|| lookup::user_name(uid)).clone()
+    }
+
+    /// Name of the group `gid`.
+    pub fn group(&mut self, gid: u32) -> Option<String> {
+        self.groups.entry(gid).or_insert_with(|| lookup::group_name(gid)).clone()
+    }
+
+    /// Id of the user called `name` on this system.
+    pub fn uid(&mut self, name: &str) -> Option<u32> {
+        *self.uids.entry(name.to_string()).or_insert_with(|| lookup::user_id(name))
+    }
+
+    /// Id of the group called `name` on this system.
+    pub fn gid(&mut self, name: &str) -> Option<u32> {
+        *self.gids.entry(name.to_string()).or_insert_with(|| lookup::group_id(name))
+    }
+}
+
+/// Owning user and group ids of a file or directory, on Unix.
+pub fn file_owner(md: &fs::Metadata) -> (Option<u32>, Option<u32>) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        (Some(md.uid()), Some(md.gid()))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
+        (None, None)
+    }
+}
+
+/// Whether this process may give files away to other owners and still set their
+/// modes and times afterwards: as root, or on Linux with `CAP_CHOWN` and
+/// `CAP_FOWNER`. Without that, restoring recorded owners can only fail.
+pub fn can_chown() -> bool {
+    #[cfg(unix)]
+    {
+        if unsafe { libc::geteuid() } == 0 {
+            return true;
+        }
+        // Effective capabilities, as a hex mask: CAP_CHOWN is bit 0, CAP_FOWNER bit 3.
+        #[cfg(target_os = "linux")]
+        if let Ok(status) = fs::read_to_string("/proc/self/status")
+            && let Some(mask) = status.lines().find_map(|l| l.strip_prefix("CapEff:"))
+            && let Ok(mask) = u64::from_str_radix(mask.trim(), 16)
+        {
+            return mask & 0b1001 == 0b1001;
+        }
+        false
+    }
+    #[cfg(not(unix))]
+    false
+}
+
+/// Give `path` (a symlink itself, not what it points to) the owner recorded on `node`.
+/// Recorded names win over ids when they exist here, so snapshots carry over between
+/// machines. Nothing is changed when the owner already matches.
+pub fn set_owner(path: &Path, node: &TreeNode, names: &mut Names) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let uid = node.user.as_deref().and_then(|n| names.uid(n)).or(node.uid);
+        let gid = node.group.as_deref().and_then(|n| names.gid(n)).or(node.gid);
+        if uid.is_none() && gid.is_none() {
+            return Ok(());
+        }
+        let md = fs::symlink_metadata(path)?;
+        let uid = uid.filter(|&u| u != md.uid());
+        let gid = gid.filter(|&g| g != md.gid());
+        if uid.is_some() || gid.is_some() {
+            std::os::unix::fs::lchown(path, uid, gid)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, node, names);
+    Ok(())
+}
+
+#[cfg(unix)]
+mod lookup {
+    use std::ffi::{CStr, CString};
+    use std::mem::MaybeUninit;
+    use std::ptr;
+
+    /// Run a reentrant passwd/group lookup, growing its buffer until the entry fits.
+    fn with_buffer<T, R>(mut call: impl FnMut(*mut T, &mut [libc::c_char], *mut *mut T) -> libc::c_int, read: impl FnOnce(&T) -> R) -> Option<R> {
+        let mut buf = vec![0 as libc::c_char; 1024];
+        loop {
+            let mut entry = MaybeUninit::<T>::uninit();
+            let mut result = ptr::null_mut();
+            match call(entry.as_mut_ptr(), &mut buf, &mut result) {
+                libc::ERANGE if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+                0 if !result.is_null() => return Some(read(unsafe { entry.assume_init_ref() })),
+                _ => return None,
+            }
+        }
+    }
+
+    fn string(p: *const libc::c_char) -> String {
+        unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned()
+    }
+
+    pub fn user_name(uid: u32) -> Option<String> {
+        with_buffer(
+            |pwd, buf, result| unsafe { libc::getpwuid_r(uid, pwd, buf.as_mut_ptr(), buf.len(), result) },
+            |pwd: &libc::passwd| string(pwd.pw_name),
+        )
+    }
+
+    pub fn group_name(gid: u32) -> Option<String> {
+        with_buffer(
+            |grp, buf, result| unsafe { libc::getgrgid_r(gid, grp, buf.as_mut_ptr(), buf.len(), result) },
+            |grp: &libc::group| string(grp.gr_name),
+        )
+    }
+
+    pub fn user_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        with_buffer(
+            |pwd, buf, result| unsafe { libc::getpwnam_r(name.as_ptr(), pwd, buf.as_mut_ptr(), buf.len(), result) },
+            |pwd: &libc::passwd| pwd.pw_uid,
+        )
+    }
+
+    pub fn group_id(name: &str) -> Option<u32> {
+        let name = CString::new(name).ok()?;
+        with_buffer(
+            |grp, buf, result| unsafe { libc::getgrnam_r(name.as_ptr(), grp, buf.as_mut_ptr(), buf.len(), result) },
+            |grp: &libc::group| grp.gr_gid,
+        )
+    }
+}
+
+#[cfg(not(unix))]
+mod lookup {
+    pub fn user_name(_: u32) -> Option<String> {
+        None
+    }
+
+    pub fn group_name(_: u32) -> Option<String> {
+        None
+    }
+
+    pub fn user_id(_: &str) -> Option<u32> {
+        None
+    }
+
+    pub fn group_id(_: &str) -> Option<u32> {
+        None
+    }
+}